@@ -0,0 +1,85 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use timedmap::{Cleanup, TimedMap, Value};
+
+const ENTRIES: u64 = 100_000;
+
+fn lifetime(i: u64) -> Duration {
+    // Every second entry is expired at the time of cleanup.
+    if i % 2 == 1 {
+        Duration::from_secs(3600)
+    } else {
+        Duration::ZERO
+    }
+}
+
+fn raw_map() -> RwLock<HashMap<u64, Value<u64, Instant>>> {
+    RwLock::new(
+        (0..ENTRIES)
+            .map(|i| (i, Value::new(i, lifetime(i))))
+            .collect(),
+    )
+}
+
+fn collect_and_remove(m: &RwLock<HashMap<u64, Value<u64, Instant>>>, now: Instant) {
+    let keys: Vec<_> = m
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, v)| v.is_expired_at(&now))
+        .map(|(k, _)| *k)
+        .collect();
+
+    let mut m = m.write().unwrap();
+    for key in keys {
+        m.remove(&key);
+    }
+}
+
+fn retain(m: &RwLock<HashMap<u64, Value<u64, Instant>>>, now: Instant) {
+    m.write().unwrap().retain(|_, v| !v.is_expired_at(&now));
+}
+
+fn cleanup(c: &mut Criterion) {
+    let mut g = c.benchmark_group("cleanup");
+
+    g.bench_function("collect_and_remove", |b| {
+        b.iter_batched(
+            raw_map,
+            |m| collect_and_remove(&m, Instant::now() + Duration::from_secs(1)),
+            BatchSize::LargeInput,
+        )
+    });
+
+    g.bench_function("retain", |b| {
+        b.iter_batched(
+            raw_map,
+            |m| retain(&m, Instant::now() + Duration::from_secs(1)),
+            BatchSize::LargeInput,
+        )
+    });
+
+    g.bench_function("timedmap", |b| {
+        b.iter_batched(
+            || {
+                let tm = TimedMap::new();
+                for i in 0..ENTRIES {
+                    tm.insert(i, i, lifetime(i));
+                }
+                std::thread::sleep(Duration::from_millis(1));
+                tm
+            },
+            |tm| tm.cleanup(),
+            BatchSize::LargeInput,
+        )
+    });
+
+    g.finish();
+}
+
+criterion_group!(benches, cleanup);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{thread, time::Duration};
+use timedmap::{ConcurrentTimedMap, TimedMap};
+
+const WRITERS: u64 = 8;
+const WRITES: u64 = 10_000;
+
+fn concurrent_inserts(c: &mut Criterion) {
+    let mut g = c.benchmark_group("concurrent_inserts");
+
+    g.bench_function("timedmap", |b| {
+        b.iter(|| {
+            let tm = TimedMap::new();
+            thread::scope(|s| {
+                for t in 0..WRITERS {
+                    let tm = &tm;
+                    s.spawn(move || {
+                        for i in 0..WRITES {
+                            tm.insert(t * WRITES + i, i, Duration::from_secs(3600));
+                        }
+                    });
+                }
+            })
+        })
+    });
+
+    g.bench_function("dashmap", |b| {
+        b.iter(|| {
+            let tm = ConcurrentTimedMap::new();
+            thread::scope(|s| {
+                for t in 0..WRITERS {
+                    let tm = &tm;
+                    s.spawn(move || {
+                        for i in 0..WRITES {
+                            tm.insert(t * WRITES + i, i, Duration::from_secs(3600));
+                        }
+                    });
+                }
+            })
+        })
+    });
+
+    g.finish();
+}
+
+criterion_group!(benches, concurrent_inserts);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+//! Compares bulk loading a map via `insert_many` with
+//! calling `insert` for every key-value pair.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::time::Duration;
+use timedmap::TimedMap;
+
+const ENTRIES: u64 = 10_000;
+const LIFETIME: Duration = Duration::from_secs(3600);
+
+fn bulk_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bulk_insert");
+
+    group.bench_function("insert_loop", |b| {
+        b.iter_batched(
+            TimedMap::new,
+            |tm| {
+                for i in 0..ENTRIES {
+                    tm.insert(i, i, LIFETIME);
+                }
+                tm
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("insert_many", |b| {
+        b.iter_batched(
+            TimedMap::new,
+            |tm| {
+                tm.insert_many((0..ENTRIES).map(|i| (i, i)), LIFETIME);
+                tm
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bulk_insert);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+//! Measures a contended, read-heavy workload. Run once with and
+//! once without the `parking-lot` feature to compare both locks.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{thread, time::Duration};
+use timedmap::TimedMap;
+
+const KEYS: u64 = 1_000;
+const READERS: usize = 4;
+const READS: u64 = 10_000;
+const WRITES: u64 = 1_000;
+
+fn contended(c: &mut Criterion) {
+    let tm = TimedMap::new();
+    for i in 0..KEYS {
+        tm.insert(i, i, Duration::from_secs(3600));
+    }
+
+    c.bench_function("contended_read_heavy", |b| {
+        b.iter(|| {
+            thread::scope(|s| {
+                for _ in 0..READERS {
+                    s.spawn(|| {
+                        for i in 0..READS {
+                            tm.get(&(i % KEYS));
+                        }
+                    });
+                }
+                s.spawn(|| {
+                    for i in 0..WRITES {
+                        tm.insert(i % KEYS, i, Duration::from_secs(3600));
+                    }
+                });
+            })
+        })
+    });
+}
+
+criterion_group!(benches, contended);
+criterion_main!(benches);
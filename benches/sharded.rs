@@ -0,0 +1,84 @@
+//! Compares concurrent writers and mixed readers and writers on
+//! the single lock of `TimedMap` with the shards of `ShardedTimedMap`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{thread, time::Duration};
+use timedmap::{ShardedTimedMap, TimedMap};
+
+const THREADS: u64 = 8;
+const OPS: u64 = 10_000;
+const LIFETIME: Duration = Duration::from_secs(3600);
+
+/// Runs `op` with `OPS` keys on each of `THREADS` threads.
+fn run(op: impl Fn(u64, u64) + Sync) {
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let op = &op;
+            s.spawn(move || (0..OPS).for_each(|i| op(t, i)));
+        }
+    })
+}
+
+fn concurrent_inserts(c: &mut Criterion) {
+    let mut g = c.benchmark_group("sharded_inserts");
+
+    g.bench_function("timedmap", |b| {
+        b.iter(|| {
+            let tm = TimedMap::new();
+            run(|t, i| {
+                tm.insert(t * OPS + i, i, LIFETIME);
+            });
+        })
+    });
+
+    g.bench_function("sharded", |b| {
+        b.iter(|| {
+            let tm = ShardedTimedMap::new();
+            run(|t, i| {
+                tm.insert(t * OPS + i, i, LIFETIME);
+            });
+        })
+    });
+
+    g.finish();
+}
+
+fn concurrent_mixed(c: &mut Criterion) {
+    let mut g = c.benchmark_group("sharded_mixed");
+
+    let tm = TimedMap::new();
+    let sharded = ShardedTimedMap::new();
+    for i in 0..OPS {
+        tm.insert(i, i, LIFETIME);
+        sharded.insert(i, i, LIFETIME);
+    }
+
+    g.bench_function("timedmap", |b| {
+        b.iter(|| {
+            run(|t, i| {
+                if i % 4 == 0 {
+                    tm.insert((t * OPS + i) % OPS, i, LIFETIME);
+                } else {
+                    tm.get(&i);
+                }
+            });
+        })
+    });
+
+    g.bench_function("sharded", |b| {
+        b.iter(|| {
+            run(|t, i| {
+                if i % 4 == 0 {
+                    sharded.insert((t * OPS + i) % OPS, i, LIFETIME);
+                } else {
+                    sharded.get(&i);
+                }
+            });
+        })
+    });
+
+    g.finish();
+}
+
+criterion_group!(benches, concurrent_inserts, concurrent_mixed);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::time::Duration;
+use timedmap::{Cleanup, TimedMap, TimedWheelMap};
+
+const EXPIRING: u64 = 100;
+const SIZES: [u64; 3] = [1_000, 10_000, 100_000];
+
+fn cleanup(c: &mut Criterion) {
+    let mut g = c.benchmark_group("cleanup_by_size");
+
+    for size in SIZES {
+        g.bench_with_input(BenchmarkId::new("timedmap", size), &size, |b, &size| {
+            b.iter_batched(
+                || {
+                    let tm = TimedMap::new();
+                    for i in 0..size {
+                        tm.insert(i, i, Duration::from_secs(3600));
+                    }
+                    for i in size..size + EXPIRING {
+                        tm.insert(i, i, Duration::ZERO);
+                    }
+                    std::thread::sleep(Duration::from_millis(2));
+                    tm
+                },
+                |tm| tm.cleanup(),
+                BatchSize::LargeInput,
+            )
+        });
+
+        g.bench_with_input(BenchmarkId::new("wheel", size), &size, |b, &size| {
+            b.iter_batched(
+                || {
+                    let tm = TimedWheelMap::new();
+                    for i in 0..size {
+                        tm.insert(i, i, Duration::from_secs(3600));
+                    }
+                    for i in size..size + EXPIRING {
+                        tm.insert(i, i, Duration::ZERO);
+                    }
+                    std::thread::sleep(Duration::from_millis(2));
+                    tm
+                },
+                |tm| tm.cleanup(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    g.finish();
+}
+
+criterion_group!(benches, cleanup);
+criterion_main!(benches);
@@ -0,0 +1,324 @@
+use crate::{
+    compat::{DefaultHashBuilder, DefaultTimeSource},
+    TimedMap, DEFAULT_SHRINK_MIN_CAPACITY, DEFAULT_SHRINK_RATIO,
+};
+use core::{fmt, marker::PhantomData, time::Duration};
+
+/// Builder for a [`TimedMap`] which bundles all construction
+/// options in one place.
+///
+/// # Example
+/// ```
+/// use timedmap::{TimedMap, TimedMapBuilder};
+/// use std::time::{Duration, Instant};
+///
+/// let tm: TimedMap<&str, i32> = TimedMapBuilder::new()
+///     .time_source::<Instant>()
+///     .default_lifetime(Some(Duration::from_secs(60)))
+///     .shrink_ratio(2.0)
+///     .min_capacity_for_shrink(64)
+///     .build();
+///
+/// tm.insert_default("foo", 1);
+/// assert_eq!(tm.get(&"foo"), Some(1));
+/// ```
+pub struct TimedMapBuilder<TS = DefaultTimeSource, S = DefaultHashBuilder> {
+    pub(crate) hasher: S,
+    pub(crate) default_lifetime: Option<Duration>,
+    pub(crate) sliding: bool,
+    pub(crate) inclusive_deadline: bool,
+    pub(crate) strict_lifetimes: bool,
+    pub(crate) track_accesses: bool,
+    pub(crate) lazy_eviction: bool,
+    pub(crate) capacity: Option<usize>,
+    pub(crate) stats: bool,
+    pub(crate) shrink: Option<(f32, usize)>,
+    time_source: PhantomData<fn() -> TS>,
+}
+
+impl TimedMapBuilder {
+    /// Creates a new builder with the default configuration,
+    /// the default [`TimeSource`](crate::time::TimeSource) and
+    /// the default hash builder.
+    pub fn new() -> Self {
+        Self {
+            hasher: DefaultHashBuilder::default(),
+            default_lifetime: None,
+            sliding: false,
+            inclusive_deadline: false,
+            strict_lifetimes: false,
+            track_accesses: false,
+            lazy_eviction: true,
+            capacity: None,
+            stats: false,
+            shrink: Some((DEFAULT_SHRINK_RATIO, DEFAULT_SHRINK_MIN_CAPACITY)),
+            time_source: PhantomData,
+        }
+    }
+}
+
+impl Default for TimedMapBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<TS, S> TimedMapBuilder<TS, S> {
+    /// Sets the [`TimeSource`](crate::time::TimeSource)
+    /// implementation used by the map.
+    pub fn time_source<TS2>(self) -> TimedMapBuilder<TS2, S> {
+        self.convert(|hasher| hasher)
+    }
+
+    /// Sets the hash builder used to hash keys.
+    pub fn hasher<S2>(self, hasher: S2) -> TimedMapBuilder<TS, S2> {
+        self.convert(|_| hasher)
+    }
+
+    /// Sets the lifetime used for key-value pairs added via
+    /// [`insert_default`](TimedMap::insert_default).
+    pub fn default_lifetime(mut self, lifetime: Option<Duration>) -> Self {
+        self.default_lifetime = lifetime;
+        self
+    }
+
+    /// Enables sliding expiration.
+    ///
+    /// See [`with_sliding_expiration`](TimedMap::with_sliding_expiration)
+    /// for details.
+    pub fn sliding_expiration(mut self) -> Self {
+        self.sliding = true;
+        self
+    }
+
+    /// Makes the deadline of key-value pairs inclusive.
+    ///
+    /// See [`with_inclusive_deadline`](TimedMap::with_inclusive_deadline)
+    /// for details.
+    pub fn inclusive_deadline(mut self) -> Self {
+        self.inclusive_deadline = true;
+        self
+    }
+
+    /// Enables strict lifetimes.
+    ///
+    /// See [`with_strict_lifetimes`](TimedMap::with_strict_lifetimes)
+    /// for details.
+    pub fn strict_lifetimes(mut self) -> Self {
+        self.strict_lifetimes = true;
+        self
+    }
+
+    /// Enables tracking accesses of key-value pairs.
+    ///
+    /// See [`with_access_tracking`](TimedMap::with_access_tracking)
+    /// for details.
+    pub fn access_tracking(mut self) -> Self {
+        self.track_accesses = true;
+        self
+    }
+
+    /// Disables removing expired key-value pairs on retrieval.
+    ///
+    /// See [`without_lazy_eviction`](TimedMap::without_lazy_eviction)
+    /// for details.
+    pub fn without_lazy_eviction(mut self) -> Self {
+        self.lazy_eviction = false;
+        self
+    }
+
+    /// Limits the number of key-value pairs in the map to `max`.
+    ///
+    /// See [`with_max_capacity`](TimedMap::with_max_capacity)
+    /// for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_capacity(mut self, max: usize) -> Self {
+        assert!(max > 0, "capacity must not be zero");
+        self.capacity = Some(max);
+        self
+    }
+
+    /// Enables collecting statistics.
+    ///
+    /// See [`with_stats`](TimedMap::with_stats) for details.
+    pub fn stats(mut self) -> Self {
+        self.stats = true;
+        self
+    }
+
+    /// Sets the ratio between the capacity and the length of the
+    /// map above which the map is shrunk after a cleanup. Defaults
+    /// to 4.
+    ///
+    /// This enables shrinking if it has been disabled via
+    /// [`without_shrinking`](Self::without_shrinking).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not positive.
+    pub fn shrink_ratio(mut self, ratio: f32) -> Self {
+        assert!(ratio > 0.0, "shrink ratio must be positive");
+        let (_, min_capacity) = self.shrink_or_default();
+        self.shrink = Some((ratio, min_capacity));
+        self
+    }
+
+    /// Sets the capacity of the map below which it is never
+    /// shrunk after a cleanup. Defaults to 1024.
+    ///
+    /// This enables shrinking if it has been disabled via
+    /// [`without_shrinking`](Self::without_shrinking).
+    pub fn min_capacity_for_shrink(mut self, min_capacity: usize) -> Self {
+        let (ratio, _) = self.shrink_or_default();
+        self.shrink = Some((ratio, min_capacity));
+        self
+    }
+
+    /// Disables shrinking the map after a cleanup.
+    pub fn without_shrinking(mut self) -> Self {
+        self.shrink = None;
+        self
+    }
+
+    /// Creates the map with the configured options.
+    pub fn build<K, V>(self) -> TimedMap<K, V, TS, S> {
+        TimedMap::from_builder(self)
+    }
+
+    fn shrink_or_default(&self) -> (f32, usize) {
+        self.shrink
+            .unwrap_or((DEFAULT_SHRINK_RATIO, DEFAULT_SHRINK_MIN_CAPACITY))
+    }
+
+    /// Converts the builder into one with other type parameters,
+    /// keeping all options.
+    fn convert<TS2, S2>(self, hasher: impl FnOnce(S) -> S2) -> TimedMapBuilder<TS2, S2> {
+        TimedMapBuilder {
+            hasher: hasher(self.hasher),
+            default_lifetime: self.default_lifetime,
+            sliding: self.sliding,
+            inclusive_deadline: self.inclusive_deadline,
+            strict_lifetimes: self.strict_lifetimes,
+            track_accesses: self.track_accesses,
+            lazy_eviction: self.lazy_eviction,
+            capacity: self.capacity,
+            stats: self.stats,
+            shrink: self.shrink,
+            time_source: PhantomData,
+        }
+    }
+}
+
+impl<TS, S: Clone> Clone for TimedMapBuilder<TS, S> {
+    fn clone(&self) -> Self {
+        Self {
+            hasher: self.hasher.clone(),
+            time_source: PhantomData,
+            ..*self
+        }
+    }
+}
+
+impl<TS, S> fmt::Debug for TimedMapBuilder<TS, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimedMapBuilder")
+            .field("default_lifetime", &self.default_lifetime)
+            .field("sliding", &self.sliding)
+            .field("inclusive_deadline", &self.inclusive_deadline)
+            .field("strict_lifetimes", &self.strict_lifetimes)
+            .field("track_accesses", &self.track_accesses)
+            .field("lazy_eviction", &self.lazy_eviction)
+            .field("capacity", &self.capacity)
+            .field("stats", &self.stats)
+            .field("shrink", &self.shrink)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Cleanup;
+    use mock_instant::{Instant, MockClock};
+
+    fn fill(tm: &TimedMap<i32, i32, Instant>) {
+        for i in 0..1_000 {
+            tm.insert(i, i, Duration::from_millis(10));
+        }
+        for i in 1_000..1_600 {
+            tm.insert(i, i, Duration::from_millis(100));
+        }
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.cleanup(), 1_000);
+    }
+
+    #[test]
+    fn shrink_ratio() {
+        let tm = TimedMapBuilder::new()
+            .time_source::<Instant>()
+            .shrink_ratio(1.5)
+            .min_capacity_for_shrink(16)
+            .build();
+        fill(&tm);
+        assert!(tm.capacity() < 1_000);
+        assert_eq!(tm.len(), 600);
+
+        let tm = TimedMapBuilder::new()
+            .time_source::<Instant>()
+            .min_capacity_for_shrink(16)
+            .build();
+        fill(&tm);
+        assert!(tm.capacity() > 1_000);
+    }
+
+    #[test]
+    fn min_capacity_for_shrink() {
+        let tm = TimedMapBuilder::new()
+            .time_source::<Instant>()
+            .shrink_ratio(1.5)
+            .min_capacity_for_shrink(100_000)
+            .build();
+        fill(&tm);
+        assert!(tm.capacity() > 1_000);
+
+        let tm = TimedMapBuilder::new()
+            .time_source::<Instant>()
+            .shrink_ratio(1.5)
+            .without_shrinking()
+            .build();
+        fill(&tm);
+        assert!(tm.capacity() > 1_000);
+    }
+
+    #[test]
+    fn options() {
+        let tm = TimedMapBuilder::new()
+            .time_source::<Instant>()
+            .default_lifetime(Some(Duration::from_millis(10)))
+            .sliding_expiration()
+            .max_capacity(2)
+            .stats()
+            .build();
+        assert_eq!(tm.default_lifetime(), Some(Duration::from_millis(10)));
+
+        tm.insert_default("a", 1);
+        MockClock::advance(Duration::from_millis(8));
+        assert_eq!(tm.get(&"a"), Some(1));
+        MockClock::advance(Duration::from_millis(8));
+        assert_eq!(tm.get(&"a"), Some(1));
+
+        tm.insert_default("b", 2);
+        tm.insert_default("c", 3);
+        assert_eq!(tm.len(), 2);
+        assert_eq!(tm.stats().hits, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "shrink ratio must be positive")]
+    fn zero_shrink_ratio() {
+        let _ = TimedMapBuilder::new().shrink_ratio(0.0);
+    }
+}
@@ -1,22 +1,34 @@
-use crate::Cleanup;
-use std::{sync::Arc, time::Duration};
-
-pub fn _start_cleaner(m: Arc<dyn Cleanup>, interval: Duration) -> Box<dyn Fn()> {
-    let job = actix_rt::spawn(async move {
-        loop {
-            actix_rt::time::sleep(interval).await;
-            m.cleanup();
-        }
-    });
-    Box::new(move || job.abort())
+use super::Spawner;
+use std::{future::Future, time::Duration};
+
+/// [`Spawner`] running cleanup cycles on the [`actix_rt`] runtime.
+///
+/// Tasks are spawned on the current actix system, so cleanup cycles
+/// have to be started from within it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ActixSpawner;
+
+impl Spawner for ActixSpawner {
+    type Sleep = actix_rt::time::Sleep;
+
+    fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        actix_rt::spawn(fut);
+    }
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        actix_rt::time::sleep(duration)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::TimedMap;
-
     use super::*;
+    use crate::{cleanup::spawner::spawn_cleaner, TimedMap};
     use actix_rt::time;
+    use std::sync::Arc;
 
     #[actix_rt::test]
     async fn cleanup() {
@@ -24,7 +36,7 @@ mod test {
         tm.insert("a", 1, Duration::from_millis(100));
         tm.insert("b", 2, Duration::from_millis(200));
 
-        let _ = _start_cleaner(tm.clone(), Duration::from_millis(10));
+        let _ = spawn_cleaner(ActixSpawner, vec![tm.clone()], Duration::from_millis(10));
 
         assert!(tm.get_value_unchecked(&"a").is_some());
         assert!(tm.get_value_unchecked(&"b").is_some());
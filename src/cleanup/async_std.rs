@@ -0,0 +1,66 @@
+use super::Spawner;
+use async_std::task;
+use std::{future::Future, pin::Pin, time::Duration};
+
+/// [`Spawner`] running cleanup cycles on the [`async_std`] runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdSpawner;
+
+impl Spawner for AsyncStdSpawner {
+    type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        task::spawn(fut);
+    }
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        Box::pin(task::sleep(duration))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cleanup::spawner::spawn_cleaner, TimedMap};
+    use std::sync::Arc;
+
+    #[async_std::test]
+    async fn cleanup() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(200));
+
+        let _ = spawn_cleaner(AsyncStdSpawner, vec![tm.clone()], Duration::from_millis(10));
+
+        assert!(tm.get_value_unchecked(&"a").is_some());
+        assert!(tm.get_value_unchecked(&"b").is_some());
+
+        task::sleep(Duration::from_millis(150)).await;
+
+        assert!(tm.get_value_unchecked(&"a").is_none());
+        assert!(tm.get_value_unchecked(&"b").is_some());
+
+        task::sleep(Duration::from_millis(60)).await;
+        assert!(tm.get_value_unchecked(&"a").is_none());
+        assert!(tm.get_value_unchecked(&"b").is_none());
+    }
+
+    #[async_std::test]
+    async fn stop() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(20));
+
+        let cleaner = spawn_cleaner(AsyncStdSpawner, vec![tm.clone()], Duration::from_millis(10));
+        assert!(cleaner.is_running());
+
+        let state = cleaner.state.clone();
+        cleaner.stop();
+
+        task::sleep(Duration::from_millis(50)).await;
+        assert!(!state.is_running());
+        assert!(tm.get_value_unchecked(&"a").is_some());
+    }
+}
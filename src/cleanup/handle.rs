@@ -0,0 +1,429 @@
+use crate::{lock::RwLock, Cleanup};
+use std::{
+    fmt,
+    future::poll_fn,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+type StopFn = Box<dyn FnOnce() + Send + Sync>;
+
+/// Handle to a running cleanup cycle, returned by
+/// [`start_cleaner`](crate::start_cleaner) and friends.
+///
+/// Dropping the handle detaches the cleanup cycle, which then
+/// keeps running in the background.
+pub struct CleanerHandle {
+    pub(super) state: Arc<State>,
+    stop: StopFn,
+}
+
+impl CleanerHandle {
+    /// Creates a new handle. `start` is passed a [`Running`] guard,
+    /// which must be moved into the started task, and returns the
+    /// function which stops the task.
+    pub(super) fn new<F>(
+        maps: Vec<Arc<dyn Cleanup>>,
+        interval: Duration,
+        start: impl FnOnce(Running) -> F,
+    ) -> Self
+    where
+        F: FnOnce() + Send + Sync + 'static,
+    {
+        let state = Arc::new(State::new(maps, interval));
+        let stop = start(Running(state.clone()));
+        Self {
+            state,
+            stop: Box::new(stop),
+        }
+    }
+
+    /// Stops the cleanup cycle.
+    ///
+    /// For the thread cleaner, this waits until the thread has
+    /// finished. Async tasks terminate in the background once they
+    /// are polled next; use [`join`](Self::join) to wait for their
+    /// termination.
+    pub fn stop(self) {
+        self.state.update(|inner| inner.stopped = true);
+        (self.stop)();
+    }
+
+    /// Returns `true` if the cleanup cycle has not yet terminated.
+    pub fn is_running(&self) -> bool {
+        self.state.is_running()
+    }
+
+    /// Returns the current interval of the cleanup cycle.
+    pub fn interval(&self) -> Duration {
+        self.state.lock().interval
+    }
+
+    /// Changes the interval of the cleanup cycle.
+    ///
+    /// A currently pending sleep is interrupted and restarted with
+    /// the new interval. For adaptive cleaners, this sets the
+    /// maximum interval.
+    pub fn set_interval(&self, interval: Duration) {
+        self.state.update(|inner| {
+            inner.interval = interval;
+            inner.changes += 1;
+        });
+    }
+
+    /// Adds another [`Cleanup`](crate::Cleanup) implementation
+    /// instance which is cleaned up by this cleanup cycle from the
+    /// next tick on.
+    pub fn add(&self, m: Arc<dyn Cleanup>) {
+        self.state.maps.write().push(Target::Strong(m));
+    }
+
+    /// Like [`add`](Self::add), but only holds a [`Weak`] reference
+    /// to the [`Cleanup`](crate::Cleanup) implementation instance.
+    ///
+    /// Once all maps of the cycle have been added this way and
+    /// have been dropped, the cleanup cycle terminates on its own.
+    pub fn add_weak(&self, m: Weak<dyn Cleanup>) {
+        self.state.maps.write().push(Target::Weak(m));
+    }
+
+    /// Stops the cleanup cycle and waits until it has terminated.
+    ///
+    /// In contrast to aborting the task, a currently running cleanup
+    /// pass is always completed, as the cycle only checks for the
+    /// stop between passes. The returned future can be awaited on
+    /// any executor.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "tokio")]
+    /// # tokio_test::block_on(async {
+    /// use timedmap::{start_cleaner, TimedMap};
+    /// use std::{sync::Arc, time::Duration};
+    ///
+    /// let tm = Arc::new(TimedMap::<&str, i32>::new());
+    /// let cleaner = start_cleaner(tm, Duration::from_secs(10));
+    ///
+    /// cleaner.shutdown().await;
+    /// # });
+    /// ```
+    pub async fn shutdown(self) {
+        let state = self.state.clone();
+        self.stop();
+        poll_fn(|cx| state.poll(cx, |inner| inner.done.then_some(()))).await
+    }
+
+    /// Waits until the cleanup cycle has terminated, either because
+    /// it has been stopped or because a cleanup panicked.
+    ///
+    /// This does not stop the cleanup cycle by itself. The returned
+    /// future can be awaited on any executor.
+    pub async fn join(self) {
+        poll_fn(|cx| self.state.poll(cx, |inner| inner.done.then_some(()))).await
+    }
+}
+
+impl fmt::Debug for CleanerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CleanerHandle")
+            .field("running", &self.is_running())
+            .field("interval", &self.interval())
+            .finish()
+    }
+}
+
+impl From<CleanerHandle> for Box<dyn Fn()> {
+    fn from(handle: CleanerHandle) -> Self {
+        let handle = Mutex::new(Some(handle));
+        Box::new(move || {
+            if let Some(handle) = handle.lock().unwrap_or_else(PoisonError::into_inner).take() {
+                handle.stop();
+            }
+        })
+    }
+}
+
+/// State shared between a [`CleanerHandle`] and its cleanup cycle.
+pub(super) struct State {
+    maps: RwLock<Vec<Target>>,
+    inner: Mutex<Inner>,
+    cvar: Condvar,
+}
+
+struct Inner {
+    interval: Duration,
+    changes: u64,
+    stopped: bool,
+    done: bool,
+    wakers: Vec<Waker>,
+    /// Time at which the last cleanup pass was due.
+    last_pass: Option<Instant>,
+}
+
+impl Inner {
+    /// Returns the time to wait from `now` until the next cleanup
+    /// pass is due.
+    ///
+    /// Passes are aligned to the interval, so the time a pass takes
+    /// does not delay the following passes. If a pass took longer
+    /// than the interval, the missed passes are skipped instead of
+    /// being run back to back.
+    fn until_next_pass(&self, now: Instant) -> Duration {
+        let Some(last_pass) = self.last_pass else {
+            return self.interval;
+        };
+        let elapsed = now.saturating_duration_since(last_pass);
+        if let Some(wait) = self.interval.checked_sub(elapsed) {
+            return wait;
+        }
+        if self.interval.is_zero() {
+            return Duration::ZERO;
+        }
+        warn!(
+            elapsed:? = elapsed, interval:? = self.interval;
+            "cleanup pass took longer than the interval"
+        );
+        let interval = self.interval.as_nanos();
+        Duration::from_nanos((interval - elapsed.as_nanos() % interval) as u64)
+    }
+}
+
+impl State {
+    fn new(maps: Vec<Arc<dyn Cleanup>>, interval: Duration) -> Self {
+        Self {
+            maps: RwLock::new(maps.into_iter().map(Target::Strong).collect()),
+            inner: Mutex::new(Inner {
+                interval,
+                changes: 0,
+                stopped: false,
+                done: false,
+                wakers: Vec::new(),
+                last_pass: None,
+            }),
+            cvar: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(super) fn is_running(&self) -> bool {
+        !self.lock().done
+    }
+
+    /// Applies `f` and wakes up everyone waiting for a change.
+    fn update(&self, f: impl FnOnce(&mut Inner)) {
+        let wakers = {
+            let mut inner = self.lock();
+            f(&mut inner);
+            std::mem::take(&mut inner.wakers)
+        };
+        self.cvar.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Returns ready once `ready` is true, otherwise registers
+    /// the waker of `cx` to be woken on the next change.
+    fn poll<T>(&self, cx: &mut Context<'_>, ready: impl FnOnce(&Inner) -> Option<T>) -> Poll<T> {
+        let mut inner = self.lock();
+        if let Some(v) = ready(&inner) {
+            return Poll::Ready(v);
+        }
+        if !inner.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            inner.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// A map which is cleaned up by a cleanup cycle.
+enum Target {
+    Strong(Arc<dyn Cleanup>),
+    Weak(Weak<dyn Cleanup>),
+}
+
+impl Target {
+    /// Returns the map or [`None`] if it has been dropped.
+    fn get(&self) -> Option<Arc<dyn Cleanup>> {
+        match self {
+            Self::Strong(m) => Some(m.clone()),
+            Self::Weak(m) => m.upgrade(),
+        }
+    }
+}
+
+/// Result of waiting for the interval of a cleanup cycle.
+pub(super) enum Wait {
+    /// The interval has elapsed.
+    Elapsed,
+    /// The interval has been changed while waiting.
+    Changed,
+    /// The cleanup cycle has been stopped.
+    Stopped,
+}
+
+/// Guard owned by a running cleanup cycle, which cleans up all maps
+/// of the cycle and marks the cycle as terminated when dropped.
+///
+/// Async runtimes drop the future of a task when shutting down, so
+/// this also covers tasks which have never been polled.
+pub(super) struct Running(Arc<State>);
+
+impl Running {
+    /// Cleans up all maps of the cycle and removes the maps which
+    /// have been dropped. Returns `false` if the last map has been
+    /// dropped, so the cycle should terminate.
+    pub(super) fn tick(&self) -> bool {
+        let mut maps = self.0.maps.write();
+        let len = maps.len();
+        maps.retain(|m| m.get().map(|m| m.cleanup()).is_some());
+        maps.len() == len || !maps.is_empty()
+    }
+
+    /// Returns the minimum time until the next element of any map
+    /// of the cycle expires.
+    pub(super) fn until_next_expiry(&self) -> Option<Duration> {
+        self.0
+            .maps
+            .read()
+            .iter()
+            .filter_map(|m| m.get()?.until_next_expiry())
+            .min()
+    }
+
+    /// Awaits the future created by `sleep` from the time until the
+    /// next cleanup pass is due. Returns [`Wait::Changed`] early if
+    /// the interval is changed in the meantime and [`Wait::Stopped`]
+    /// once the cycle has been stopped.
+    pub(super) async fn sleep<F: std::future::Future>(
+        &self,
+        sleep: impl FnOnce(Duration) -> F,
+    ) -> Wait {
+        let now = Instant::now();
+        let (interval, changes) = {
+            let inner = self.0.lock();
+            (inner.until_next_pass(now), inner.changes)
+        };
+        let mut sleep = std::pin::pin!(sleep(interval));
+        let wait = poll_fn(|cx| {
+            let changed = self.0.poll(cx, |inner| {
+                if inner.stopped {
+                    Some(Wait::Stopped)
+                } else {
+                    (inner.changes != changes).then_some(Wait::Changed)
+                }
+            });
+            if changed.is_ready() {
+                return changed;
+            }
+            sleep.as_mut().poll(cx).map(|_| Wait::Elapsed)
+        })
+        .await;
+        if let Wait::Elapsed = wait {
+            self.0.lock().last_pass = Some(now + interval);
+        }
+        wait
+    }
+
+    /// Blocks the current thread until the next cleanup pass is due,
+    /// the interval is changed or the cycle is stopped.
+    pub(super) fn wait(&self) -> Wait {
+        let now = Instant::now();
+        let inner = self.0.lock();
+        let (interval, changes) = (inner.until_next_pass(now), inner.changes);
+        let (mut inner, _) = self
+            .0
+            .cvar
+            .wait_timeout_while(inner, interval, |inner| {
+                !inner.stopped && inner.changes == changes
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+        if inner.stopped {
+            Wait::Stopped
+        } else if inner.changes != changes {
+            Wait::Changed
+        } else {
+            inner.last_pass = Some(now + interval);
+            Wait::Elapsed
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.update(|inner| inner.done = true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    fn start() -> CleanerHandle {
+        CleanerHandle::new(vec![], Duration::from_secs(60), |running| {
+            let job = thread::spawn(move || while !matches!(running.wait(), Wait::Stopped) {});
+            move || {
+                let _ = job.join();
+            }
+        })
+    }
+
+    #[test]
+    fn stop() {
+        let handle = start();
+        assert!(handle.is_running());
+
+        let state = handle.state.clone();
+        handle.stop();
+        assert!(!state.is_running());
+    }
+
+    #[test]
+    fn join() {
+        let handle = CleanerHandle::new(vec![], Duration::from_secs(60), |running| {
+            thread::spawn(move || {
+                let _running = running;
+                thread::sleep(Duration::from_millis(20));
+            });
+            || {}
+        });
+        assert!(handle.is_running());
+
+        smol::block_on(handle.join());
+    }
+
+    #[test]
+    fn into_fn() {
+        let handle = start();
+        let state = handle.state.clone();
+
+        let stop: Box<dyn Fn()> = handle.into();
+        stop();
+        stop();
+        assert!(!state.is_running());
+    }
+
+    #[test]
+    fn until_next_pass() {
+        let state = State::new(vec![], Duration::from_millis(50));
+        let mut inner = state.lock();
+        let now = Instant::now();
+        assert_eq!(inner.until_next_pass(now), Duration::from_millis(50));
+
+        inner.last_pass = Some(now);
+        let at = |ms| now + Duration::from_millis(ms);
+        assert_eq!(inner.until_next_pass(at(20)), Duration::from_millis(30));
+        assert_eq!(inner.until_next_pass(at(50)), Duration::ZERO);
+        assert_eq!(inner.until_next_pass(at(70)), Duration::from_millis(30));
+        assert_eq!(inner.until_next_pass(at(180)), Duration::from_millis(20));
+
+        inner.last_pass = Some(at(100));
+        assert_eq!(inner.until_next_pass(now), Duration::from_millis(50));
+
+        inner.interval = Duration::ZERO;
+        assert_eq!(inner.until_next_pass(at(120)), Duration::ZERO);
+    }
+}
@@ -1,29 +1,118 @@
+#[cfg(not(feature = "no-std"))]
+mod handle;
+#[cfg(not(feature = "no-std"))]
+mod spawner;
+#[cfg(not(feature = "no-std"))]
+mod thread;
+
+#[cfg(not(feature = "no-std"))]
+pub use self::handle::CleanerHandle;
+#[cfg(not(feature = "no-std"))]
+use self::handle::Wait;
+#[cfg(not(feature = "no-std"))]
+pub use self::spawner::{
+    start_adaptive_cleaner_on, start_async_cleaner_on, start_cleaner_on, Spawner,
+};
+
 #[cfg(feature = "actix-rt")]
 pub mod actixrt;
 #[cfg(feature = "actix-rt")]
-use self::actixrt::_start_cleaner;
+pub use self::actixrt::ActixSpawner;
+#[cfg(all(feature = "actix-rt", not(feature = "tokio")))]
+use self::actixrt::ActixSpawner as DefaultSpawner;
 
 #[cfg(feature = "tokio")]
 mod tokio;
 #[cfg(feature = "tokio")]
-use self::tokio::_start_cleaner;
+pub use self::tokio::TokioSpawner;
+#[cfg(feature = "tokio")]
+use self::tokio::TokioSpawner as DefaultSpawner;
+
+#[cfg(feature = "async-std")]
+mod async_std;
+#[cfg(feature = "async-std")]
+pub use self::async_std::AsyncStdSpawner;
+#[cfg(all(
+    feature = "async-std",
+    not(any(feature = "tokio", feature = "actix-rt"))
+))]
+use self::async_std::AsyncStdSpawner as DefaultSpawner;
+
+#[cfg(feature = "smol")]
+mod smol;
+#[cfg(feature = "smol")]
+pub use self::smol::SmolSpawner;
+#[cfg(all(
+    feature = "smol",
+    not(any(feature = "tokio", feature = "actix-rt", feature = "async-std"))
+))]
+use self::smol::SmolSpawner as DefaultSpawner;
 
 /// Cleanup defines an implementation where expired
 /// elements can be removed.
 pub trait Cleanup: Send + Sync {
     /// Cleanup removes all elements
     /// which have been expired.
-    fn cleanup(&self);
+    ///
+    /// Returns the number of removed elements.
+    fn cleanup(&self) -> usize;
+
+    /// Returns the time until the next element
+    /// expires or [`None`] if it is unknown or
+    /// there are no elements which can expire.
+    fn until_next_expiry(&self) -> Option<core::time::Duration> {
+        None
+    }
+}
+
+/// Like [`Cleanup`], but for implementations which remove
+/// expired elements asynchronously, for example because they
+/// are guarded by async locks.
+///
+/// Such implementations can be cleaned up by a cleanup cycle
+/// started via `start_async_cleaner` or [`start_async_cleaner_on`]
+/// without blocking the task of the cycle.
+///
+/// # Example
+/// ```
+/// use timedmap::AsyncCleanup;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// struct Counter(AtomicUsize);
+///
+/// impl AsyncCleanup for Counter {
+///     async fn cleanup(&self) -> usize {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///         0
+///     }
+/// }
+/// ```
+pub trait AsyncCleanup: Send + Sync {
+    /// Removes all elements which have been expired.
+    ///
+    /// Returns the number of removed elements.
+    fn cleanup(&self) -> impl core::future::Future<Output = usize> + Send;
 }
 
-#[cfg(any(feature = "tokio", feature = "actix-rt"))]
+#[cfg(any(
+    feature = "tokio",
+    feature = "actix-rt",
+    feature = "async-std",
+    feature = "smol"
+))]
 /// Start a new cleanup cycle on the given [`Cleanup`](crate::Cleanup)
-/// implementation instance and returns a function to cancel the
-/// cleanup cycle.
+/// implementation instance and returns a [`CleanerHandle`] to stop
+/// the cleanup cycle.
 ///
 /// On each elapse, the map ich checked for expired
 /// key-value pairs and removes them from the map.
 ///
+/// The cleanup cycle runs on the runtime of the enabled feature.
+/// When multiple runtime features are enabled, the first one of
+/// `tokio`, `actix-rt`, `async-std` and `smol` is used. Use
+/// [`start_cleaner_on`] or one of the runtime specific functions
+/// like [`start_tokio_cleaner`] to choose the runtime explicitly.
+///
 /// # Example
 /// ```
 /// use timedmap::{TimedMap, start_cleaner};
@@ -35,14 +124,288 @@ pub trait Cleanup: Send + Sync {
 ///
 /// # #[cfg(feature = "tokio")]
 /// # tokio_test::block_on(async {
-/// let cancel = start_cleaner(tm, Duration::from_secs(10));
+/// let cleaner = start_cleaner(tm, Duration::from_secs(10));
+/// assert!(cleaner.is_running());
 ///
-/// cancel();
+/// cleaner.stop();
 /// # });
 /// ```
 pub fn start_cleaner(
     m: std::sync::Arc<dyn Cleanup>,
     interval: std::time::Duration,
-) -> Box<dyn Fn()> {
-    _start_cleaner(m, interval)
+) -> CleanerHandle {
+    self::spawner::spawn_cleaner(DefaultSpawner, vec![m], interval)
+}
+
+#[cfg(any(
+    feature = "tokio",
+    feature = "actix-rt",
+    feature = "async-std",
+    feature = "smol"
+))]
+/// Start a new cleanup cycle on all given [`Cleanup`](crate::Cleanup)
+/// implementation instances and returns a [`CleanerHandle`] to stop
+/// the cleanup cycle.
+///
+/// In contrast to starting a cleaner per map, all maps are cleaned
+/// up one after another on each elapse of a single timer. Further
+/// maps can be registered using [`CleanerHandle::add`].
+///
+/// # Example
+/// ```
+/// use timedmap::{TimedMap, start_multi_cleaner};
+/// use std::time::Duration;
+/// use std::sync::Arc;
+///
+/// let a = Arc::new(TimedMap::new());
+/// a.insert("foo", "bar", Duration::from_secs(60));
+/// let b = Arc::new(TimedMap::new());
+/// b.insert(1, 2, Duration::from_secs(60));
+///
+/// # #[cfg(feature = "tokio")]
+/// # tokio_test::block_on(async {
+/// let cleaner = start_multi_cleaner(vec![a, b], Duration::from_secs(10));
+///
+/// cleaner.stop();
+/// # });
+/// ```
+pub fn start_multi_cleaner(
+    maps: Vec<std::sync::Arc<dyn Cleanup>>,
+    interval: std::time::Duration,
+) -> CleanerHandle {
+    self::spawner::spawn_cleaner(DefaultSpawner, maps, interval)
+}
+
+#[cfg(any(
+    feature = "tokio",
+    feature = "actix-rt",
+    feature = "async-std",
+    feature = "smol"
+))]
+/// Start a new cleanup cycle on the given [`Cleanup`](crate::Cleanup)
+/// implementation instance like [`start_cleaner`], but only holds a
+/// [`Weak`](std::sync::Weak) reference to it.
+///
+/// Thereby, the cleaner does not keep the map alive. Once the map
+/// has been dropped, the cleanup cycle terminates on its own.
+///
+/// # Example
+/// ```
+/// use timedmap::{TimedMap, start_cleaner_weak};
+/// use std::time::Duration;
+/// use std::sync::Arc;
+///
+/// let tm = Arc::new(TimedMap::new());
+/// tm.insert("foo", "bar", Duration::from_secs(60));
+///
+/// # #[cfg(feature = "tokio")]
+/// # tokio_test::block_on(async {
+/// let cleaner = start_cleaner_weak(Arc::downgrade(&tm) as _, Duration::from_secs(10));
+///
+/// // Dropping the map terminates the cleaner on the next elapse.
+/// drop(tm);
+/// # cleaner.stop();
+/// # });
+/// ```
+pub fn start_cleaner_weak(
+    m: std::sync::Weak<dyn Cleanup>,
+    interval: std::time::Duration,
+) -> CleanerHandle {
+    let handle = self::spawner::spawn_cleaner(DefaultSpawner, vec![], interval);
+    handle.add_weak(m);
+    handle
+}
+
+#[cfg(any(
+    feature = "tokio",
+    feature = "actix-rt",
+    feature = "async-std",
+    feature = "smol"
+))]
+/// Start a new cleanup cycle on the given [`AsyncCleanup`]
+/// implementation instance and returns a [`CleanerHandle`] to stop
+/// the cleanup cycle.
+///
+/// This behaves like [`start_cleaner`], but awaits the cleanup
+/// instead of blocking the task of the cycle. Maps registered via
+/// [`CleanerHandle::add`] are cleaned up after it on each elapse.
+///
+/// # Example
+/// ```
+/// use timedmap::{start_async_cleaner, AsyncCleanup};
+/// use std::{sync::Arc, time::Duration};
+///
+/// struct Noop;
+///
+/// impl AsyncCleanup for Noop {
+///     async fn cleanup(&self) -> usize {
+///         0
+///     }
+/// }
+///
+/// # #[cfg(feature = "tokio")]
+/// # tokio_test::block_on(async {
+/// let cleaner = start_async_cleaner(Arc::new(Noop), Duration::from_secs(10));
+/// cleaner.shutdown().await;
+/// # });
+/// ```
+pub fn start_async_cleaner<C: AsyncCleanup + 'static>(
+    m: std::sync::Arc<C>,
+    interval: std::time::Duration,
+) -> CleanerHandle {
+    start_async_cleaner_on(DefaultSpawner, m, interval)
+}
+
+/// Like [`start_cleaner`], but always runs the cleanup cycle on
+/// the [`tokio`](::tokio) runtime.
+#[cfg(feature = "tokio")]
+pub fn start_tokio_cleaner(
+    m: std::sync::Arc<dyn Cleanup>,
+    interval: std::time::Duration,
+) -> CleanerHandle {
+    start_cleaner_on(TokioSpawner, m, interval)
+}
+
+/// Like [`start_cleaner`], but always runs the cleanup cycle on
+/// the [`actix_rt`](::actix_rt) runtime.
+#[cfg(feature = "actix-rt")]
+pub fn start_actix_cleaner(
+    m: std::sync::Arc<dyn Cleanup>,
+    interval: std::time::Duration,
+) -> CleanerHandle {
+    start_cleaner_on(ActixSpawner, m, interval)
+}
+
+/// Like [`start_cleaner`], but always runs the cleanup cycle on
+/// the [`async_std`](::async_std) runtime.
+#[cfg(feature = "async-std")]
+pub fn start_async_std_cleaner(
+    m: std::sync::Arc<dyn Cleanup>,
+    interval: std::time::Duration,
+) -> CleanerHandle {
+    start_cleaner_on(AsyncStdSpawner, m, interval)
+}
+
+/// Like [`start_cleaner`], but always runs the cleanup cycle on
+/// the global [`smol`](::smol) executor.
+#[cfg(feature = "smol")]
+pub fn start_smol_cleaner(
+    m: std::sync::Arc<dyn Cleanup>,
+    interval: std::time::Duration,
+) -> CleanerHandle {
+    start_cleaner_on(SmolSpawner, m, interval)
+}
+
+/// Start a new cleanup cycle on the given [`Cleanup`](crate::Cleanup)
+/// implementation instance on a separate thread and returns a
+/// [`CleanerHandle`] to stop the cleanup cycle.
+///
+/// In contrast to `start_cleaner`, this does not require an async
+/// runtime. Stopping the returned handle stops the thread and waits
+/// until it has finished.
+///
+/// # Example
+/// ```
+/// use timedmap::{TimedMap, start_thread_cleaner};
+/// use std::time::Duration;
+/// use std::sync::Arc;
+///
+/// let tm = Arc::new(TimedMap::new());
+/// tm.insert("foo", "bar", Duration::from_secs(60));
+///
+/// let cleaner = start_thread_cleaner(tm, Duration::from_secs(10));
+/// assert!(cleaner.is_running());
+///
+/// cleaner.stop();
+/// ```
+#[cfg(not(feature = "no-std"))]
+pub fn start_thread_cleaner(
+    m: std::sync::Arc<dyn Cleanup>,
+    interval: std::time::Duration,
+) -> CleanerHandle {
+    self::thread::_start_cleaner(vec![m], interval)
+}
+
+#[cfg(any(
+    feature = "tokio",
+    feature = "actix-rt",
+    feature = "async-std",
+    feature = "smol"
+))]
+/// Start a new cleanup cycle on the given [`Cleanup`](crate::Cleanup)
+/// implementation instance which sleeps until the next element
+/// expires instead of waking up in fixed intervals. Returns a
+/// [`CleanerHandle`] to stop the cleanup cycle.
+///
+/// The time slept between two cleanups is clamped between
+/// `min_interval` and `max_interval`. When there are no elements
+/// which can expire, the cycle sleeps for `max_interval` before
+/// checking again. This is also the case for [`Cleanup`](crate::Cleanup)
+/// implementations which do not provide
+/// [`until_next_expiry`](crate::Cleanup::until_next_expiry).
+///
+/// # Example
+/// ```
+/// use timedmap::{TimedMap, start_adaptive_cleaner};
+/// use std::time::Duration;
+/// use std::sync::Arc;
+///
+/// let tm = Arc::new(TimedMap::new());
+/// tm.insert("foo", "bar", Duration::from_secs(60));
+///
+/// # #[cfg(feature = "tokio")]
+/// # tokio_test::block_on(async {
+/// let cleaner = start_adaptive_cleaner(
+///     tm,
+///     Duration::from_millis(100),
+///     Duration::from_secs(10),
+/// );
+///
+/// cleaner.stop();
+/// # });
+/// ```
+pub fn start_adaptive_cleaner(
+    m: std::sync::Arc<dyn Cleanup>,
+    min_interval: std::time::Duration,
+    max_interval: std::time::Duration,
+) -> CleanerHandle {
+    self::spawner::spawn_adaptive_cleaner(DefaultSpawner, vec![m], min_interval, max_interval)
+}
+
+#[cfg(not(feature = "no-std"))]
+fn adaptive_interval(
+    until_next_expiry: Option<std::time::Duration>,
+    min_interval: std::time::Duration,
+    max_interval: std::time::Duration,
+) -> std::time::Duration {
+    until_next_expiry
+        .unwrap_or(max_interval)
+        .clamp(min_interval, max_interval.max(min_interval))
+}
+
+#[cfg(all(test, feature = "tokio", feature = "actix-rt"))]
+mod test {
+    use super::*;
+    use crate::TimedMap;
+    use std::{sync::Arc, time::Duration};
+
+    #[actix_rt::test]
+    async fn multiple_runtimes() {
+        let a = Arc::new(TimedMap::new());
+        a.insert("a", 1, Duration::from_millis(20));
+        let b = Arc::new(TimedMap::new());
+        b.insert("b", 2, Duration::from_millis(20));
+
+        let tokio = start_tokio_cleaner(a.clone(), Duration::from_millis(10));
+        let actix = start_actix_cleaner(b.clone(), Duration::from_millis(10));
+        let default = start_cleaner(Arc::new(TimedMap::<(), ()>::new()), Duration::from_secs(1));
+
+        actix_rt::time::sleep(Duration::from_millis(60)).await;
+        assert!(a.get_value_unchecked(&"a").is_none());
+        assert!(b.get_value_unchecked(&"b").is_none());
+
+        tokio.stop();
+        actix.stop();
+        default.stop();
+    }
 }
@@ -0,0 +1,72 @@
+use super::Spawner;
+use smol::Timer;
+use std::{future::Future, pin::Pin, time::Duration};
+
+/// [`Spawner`] running cleanup cycles on the global [`smol`] executor.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmolSpawner;
+
+impl Spawner for SmolSpawner {
+    type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        smol::spawn(fut).detach();
+    }
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        Box::pin(async move {
+            Timer::after(duration).await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cleanup::spawner::spawn_cleaner, TimedMap};
+    use std::sync::Arc;
+
+    #[test]
+    fn cleanup() {
+        smol::block_on(async {
+            let tm = Arc::new(TimedMap::new());
+            tm.insert("a", 1, Duration::from_millis(100));
+            tm.insert("b", 2, Duration::from_millis(200));
+
+            let _cleaner = spawn_cleaner(SmolSpawner, vec![tm.clone()], Duration::from_millis(10));
+
+            assert!(tm.get_value_unchecked(&"a").is_some());
+            assert!(tm.get_value_unchecked(&"b").is_some());
+
+            Timer::after(Duration::from_millis(150)).await;
+
+            assert!(tm.get_value_unchecked(&"a").is_none());
+            assert!(tm.get_value_unchecked(&"b").is_some());
+
+            Timer::after(Duration::from_millis(60)).await;
+            assert!(tm.get_value_unchecked(&"a").is_none());
+            assert!(tm.get_value_unchecked(&"b").is_none());
+        });
+    }
+
+    #[test]
+    fn stop() {
+        smol::block_on(async {
+            let tm = Arc::new(TimedMap::new());
+            tm.insert("a", 1, Duration::from_millis(20));
+
+            let cleaner = spawn_cleaner(SmolSpawner, vec![tm.clone()], Duration::from_millis(10));
+            assert!(cleaner.is_running());
+
+            let state = cleaner.state.clone();
+            cleaner.stop();
+
+            Timer::after(Duration::from_millis(50)).await;
+            assert!(!state.is_running());
+            assert!(tm.get_value_unchecked(&"a").is_some());
+        });
+    }
+}
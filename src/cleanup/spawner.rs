@@ -0,0 +1,283 @@
+use super::{adaptive_interval, CleanerHandle, Wait};
+use crate::{AsyncCleanup, Cleanup};
+use std::{future::Future, sync::Arc, time::Duration};
+
+/// Abstraction over an async runtime, which is used to run
+/// the cleanup cycles started via [`start_cleaner_on`].
+///
+/// Implementations for the supported runtimes are provided when
+/// the corresponding feature is enabled, but any other runtime can
+/// be used by implementing this trait.
+///
+/// # Example
+/// ```
+/// use std::{future::Future, time::Duration};
+/// use timedmap::Spawner;
+///
+/// struct MyRuntime;
+///
+/// impl Spawner for MyRuntime {
+///     type Sleep = tokio::time::Sleep;
+///
+///     fn spawn<F>(&self, fut: F)
+///     where
+///         F: Future<Output = ()> + Send + 'static,
+///     {
+///         tokio::spawn(fut);
+///     }
+///
+///     fn sleep(&self, duration: Duration) -> Self::Sleep {
+///         tokio::time::sleep(duration)
+///     }
+/// }
+/// ```
+pub trait Spawner: Send + Sync + 'static {
+    /// Future returned from [`sleep`](Spawner::sleep).
+    type Sleep: Future<Output = ()> + Send;
+
+    /// Spawns the given future as a detached task.
+    fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static;
+
+    /// Returns a future which completes after the given duration.
+    fn sleep(&self, duration: Duration) -> Self::Sleep;
+}
+
+/// Start a new cleanup cycle on the given [`Cleanup`](crate::Cleanup)
+/// implementation instance using the given [`Spawner`] and returns
+/// a [`CleanerHandle`] to stop the cleanup cycle.
+///
+/// This behaves like `start_cleaner`, but
+/// does not depend on the runtime selected via the enabled features.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # tokio_test::block_on(async {
+/// use timedmap::{TimedMap, TokioSpawner, start_cleaner_on};
+/// use std::time::Duration;
+/// use std::sync::Arc;
+///
+/// let tm = Arc::new(TimedMap::new());
+/// tm.insert("foo", "bar", Duration::from_secs(60));
+///
+/// let cleaner = start_cleaner_on(TokioSpawner, tm, Duration::from_secs(10));
+/// assert!(cleaner.is_running());
+///
+/// cleaner.stop();
+/// # });
+/// ```
+pub fn start_cleaner_on<S: Spawner>(
+    spawner: S,
+    m: Arc<dyn Cleanup>,
+    interval: Duration,
+) -> CleanerHandle {
+    spawn_cleaner(spawner, vec![m], interval)
+}
+
+/// Start a new cleanup cycle on the given [`Cleanup`](crate::Cleanup)
+/// implementation instance using the given [`Spawner`], which sleeps
+/// until the next element expires. Returns a [`CleanerHandle`] to
+/// stop the cleanup cycle.
+///
+/// See `start_adaptive_cleaner`
+/// for details.
+pub fn start_adaptive_cleaner_on<S: Spawner>(
+    spawner: S,
+    m: Arc<dyn Cleanup>,
+    min_interval: Duration,
+    max_interval: Duration,
+) -> CleanerHandle {
+    spawn_adaptive_cleaner(spawner, vec![m], min_interval, max_interval)
+}
+
+/// Start a new cleanup cycle on the given [`AsyncCleanup`]
+/// implementation instance using the given [`Spawner`] and returns
+/// a [`CleanerHandle`] to stop the cleanup cycle.
+///
+/// See `start_async_cleaner` for details.
+pub fn start_async_cleaner_on<S: Spawner, C: AsyncCleanup + 'static>(
+    spawner: S,
+    m: Arc<C>,
+    interval: Duration,
+) -> CleanerHandle {
+    CleanerHandle::new(vec![], interval, |running| {
+        let spawner = Arc::new(spawner);
+        spawner.clone().spawn(async move {
+            loop {
+                match running.sleep(|d| spawner.sleep(d)).await {
+                    Wait::Elapsed => {
+                        m.cleanup().await;
+                        running.tick();
+                    }
+                    Wait::Changed => {}
+                    Wait::Stopped => break,
+                }
+            }
+        });
+        || {}
+    })
+}
+
+/// Spawns a cleanup cycle on all given maps.
+///
+/// The task terminates on its own once the cycle has been stopped,
+/// so there is no need to cancel it.
+pub(super) fn spawn_cleaner<S: Spawner>(
+    spawner: S,
+    maps: Vec<Arc<dyn Cleanup>>,
+    interval: Duration,
+) -> CleanerHandle {
+    CleanerHandle::new(maps, interval, |running| {
+        let spawner = Arc::new(spawner);
+        spawner.clone().spawn(async move {
+            loop {
+                match running.sleep(|d| spawner.sleep(d)).await {
+                    Wait::Elapsed if !running.tick() => break,
+                    Wait::Elapsed | Wait::Changed => {}
+                    Wait::Stopped => break,
+                }
+            }
+        });
+        || {}
+    })
+}
+
+/// Like [`spawn_cleaner`], but sleeps until the next expiry of any
+/// map, clamped between `min_interval` and `max_interval`.
+pub(super) fn spawn_adaptive_cleaner<S: Spawner>(
+    spawner: S,
+    maps: Vec<Arc<dyn Cleanup>>,
+    min_interval: Duration,
+    max_interval: Duration,
+) -> CleanerHandle {
+    CleanerHandle::new(maps, max_interval, |running| {
+        let spawner = Arc::new(spawner);
+        spawner.clone().spawn(async move {
+            loop {
+                let sleep = |max_interval| {
+                    spawner.sleep(adaptive_interval(
+                        running.until_next_expiry(),
+                        min_interval,
+                        max_interval,
+                    ))
+                };
+                match running.sleep(sleep).await {
+                    Wait::Elapsed if !running.tick() => break,
+                    Wait::Elapsed | Wait::Changed => {}
+                    Wait::Stopped => break,
+                }
+            }
+        });
+        || {}
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::TimedMap;
+    use std::{
+        future::{pending, ready},
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+    };
+
+    /// Runs spawned futures on a separate thread. Only the first
+    /// sleep completes, all further sleeps never complete.
+    #[derive(Default)]
+    struct MockSpawner {
+        sleeps: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl Spawner for MockSpawner {
+        type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+        fn spawn<F>(&self, fut: F)
+        where
+            F: Future<Output = ()> + Send + 'static,
+        {
+            thread::spawn(move || smol::block_on(fut));
+        }
+
+        fn sleep(&self, duration: Duration) -> Self::Sleep {
+            let mut sleeps = self.sleeps.lock().unwrap();
+            sleeps.push(duration);
+            if sleeps.len() == 1 {
+                Box::pin(ready(()))
+            } else {
+                Box::pin(pending())
+            }
+        }
+    }
+
+    #[test]
+    fn cleanup() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::ZERO);
+        tm.insert("b", 2, Duration::from_secs(60));
+        thread::sleep(Duration::from_millis(1));
+
+        let spawner = MockSpawner::default();
+        let sleeps = spawner.sleeps.clone();
+        let cleaner = start_cleaner_on(spawner, tm.clone(), Duration::from_secs(10));
+
+        for _ in 0..100 {
+            if sleeps.lock().unwrap().len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*sleeps.lock().unwrap(), [Duration::from_secs(10); 2]);
+        assert!(tm.get_value_unchecked(&"a").is_none());
+        assert!(tm.get_value_unchecked(&"b").is_some());
+
+        let state = cleaner.state.clone();
+        cleaner.stop();
+        for _ in 0..100 {
+            if !state.is_running() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(!state.is_running());
+    }
+
+    #[test]
+    fn async_cleanup() {
+        #[derive(Default)]
+        struct Mock {
+            calls: AtomicUsize,
+        }
+
+        impl AsyncCleanup for Mock {
+            async fn cleanup(&self) -> usize {
+                smol::future::yield_now().await;
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                0
+            }
+        }
+
+        let m = Arc::new(Mock::default());
+
+        let spawner = MockSpawner::default();
+        let sleeps = spawner.sleeps.clone();
+        let cleaner = start_async_cleaner_on(spawner, m.clone(), Duration::from_secs(10));
+
+        for _ in 0..100 {
+            if sleeps.lock().unwrap().len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(m.calls.load(Ordering::SeqCst), 1);
+
+        smol::block_on(cleaner.shutdown());
+        assert_eq!(m.calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,115 @@
+use super::{CleanerHandle, Wait};
+use crate::Cleanup;
+use std::{sync::Arc, thread, time::Duration};
+
+pub fn _start_cleaner(maps: Vec<Arc<dyn Cleanup>>, interval: Duration) -> CleanerHandle {
+    CleanerHandle::new(maps, interval, |running| {
+        let job = thread::spawn(move || loop {
+            // The state is not locked while cleaning up, so a stop
+            // request is not blocked by a running tick. Stopping the
+            // cleaner still joins this thread, so it returns only
+            // after that tick has finished.
+            match running.wait() {
+                Wait::Elapsed if !running.tick() => break,
+                Wait::Elapsed | Wait::Changed => {}
+                Wait::Stopped => break,
+            }
+        });
+
+        move || {
+            let _ = job.join();
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::TimedMap;
+
+    #[test]
+    fn cleanup() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(200));
+
+        let cleaner = _start_cleaner(vec![tm.clone()], Duration::from_millis(10));
+
+        assert!(tm.get_value_unchecked(&"a").is_some());
+        assert!(tm.get_value_unchecked(&"b").is_some());
+
+        thread::sleep(Duration::from_millis(150));
+
+        assert!(tm.get_value_unchecked(&"a").is_none());
+        assert!(tm.get_value_unchecked(&"b").is_some());
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(tm.get_value_unchecked(&"a").is_none());
+        assert!(tm.get_value_unchecked(&"b").is_none());
+
+        cleaner.stop();
+    }
+
+    #[test]
+    fn stop() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(20));
+
+        let cleaner = _start_cleaner(vec![tm.clone()], Duration::from_secs(60));
+        assert!(cleaner.is_running());
+
+        let state = cleaner.state.clone();
+        cleaner.stop();
+        assert!(!state.is_running());
+        drop(state);
+
+        // The cleaner thread has been joined and released its reference.
+        assert_eq!(Arc::strong_count(&tm), 1);
+    }
+
+    #[test]
+    fn stop_after_panicking_cleanup() {
+        struct Panicking;
+
+        impl Cleanup for Panicking {
+            fn cleanup(&self) -> usize {
+                panic!("cleanup failed");
+            }
+        }
+
+        let cleaner = _start_cleaner(vec![Arc::new(Panicking)], Duration::from_millis(1));
+        while cleaner.is_running() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        cleaner.stop();
+    }
+
+    #[test]
+    fn set_interval() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(10));
+
+        let cleaner = _start_cleaner(vec![tm.clone()], Duration::from_secs(60));
+        thread::sleep(Duration::from_millis(50));
+        assert!(tm.get_value_unchecked(&"a").is_some());
+
+        cleaner.set_interval(Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(50));
+        assert!(tm.get_value_unchecked(&"a").is_none());
+
+        cleaner.stop();
+    }
+
+    #[test]
+    fn weak_cleanup() {
+        let tm = Arc::new(TimedMap::<i32, i32>::new());
+
+        let cleaner = _start_cleaner(vec![], Duration::from_millis(1));
+        cleaner.add_weak(Arc::downgrade(&tm) as _);
+
+        drop(tm);
+        while cleaner.is_running() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
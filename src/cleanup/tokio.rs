@@ -1,20 +1,36 @@
-use crate::Cleanup;
-use std::{sync::Arc, time::Duration};
+use super::Spawner;
+use std::{future::Future, time::Duration};
 
-pub fn _start_cleaner(m: Arc<dyn Cleanup>, interval: Duration) -> Box<dyn Fn()> {
-    let job = tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(interval).await;
-            m.cleanup();
-        }
-    });
-    Box::new(move || job.abort())
+/// [`Spawner`] running cleanup cycles on the [`tokio`] runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioSpawner;
+
+impl Spawner for TokioSpawner {
+    type Sleep = tokio::time::Sleep;
+
+    fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(fut);
+    }
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        tokio::time::sleep(duration)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::TimedMap;
+    use crate::{
+        cleanup::spawner::{spawn_adaptive_cleaner, spawn_cleaner},
+        Cleanup, TimedMap,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
     use tokio::time;
 
     #[tokio::test]
@@ -23,7 +39,7 @@ mod test {
         tm.insert("a", 1, Duration::from_millis(100));
         tm.insert("b", 2, Duration::from_millis(200));
 
-        let _ = _start_cleaner(tm.clone(), Duration::from_millis(10));
+        let _ = spawn_cleaner(TokioSpawner, vec![tm.clone()], Duration::from_millis(10));
 
         assert!(tm.get_value_unchecked(&"a").is_some());
         assert!(tm.get_value_unchecked(&"b").is_some());
@@ -37,4 +53,252 @@ mod test {
         assert!(tm.get_value_unchecked(&"a").is_none());
         assert!(tm.get_value_unchecked(&"b").is_none());
     }
+
+    #[tokio::test]
+    async fn adaptive_cleanup() {
+        struct Counting {
+            tm: TimedMap<&'static str, i32>,
+            calls: AtomicUsize,
+        }
+
+        impl Cleanup for Counting {
+            fn cleanup(&self) -> usize {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                self.tm.cleanup()
+            }
+
+            fn until_next_expiry(&self) -> Option<Duration> {
+                self.tm.until_next_expiry()
+            }
+        }
+
+        let c = Arc::new(Counting {
+            tm: TimedMap::new(),
+            calls: AtomicUsize::new(0),
+        });
+        c.tm.insert("a", 1, Duration::from_millis(50));
+        c.tm.insert("b", 2, Duration::from_millis(150));
+
+        let cleaner = spawn_adaptive_cleaner(
+            TokioSpawner,
+            vec![c.clone()],
+            Duration::from_millis(1),
+            Duration::from_secs(10),
+        );
+
+        time::sleep(Duration::from_millis(80)).await;
+        assert!(c.tm.get_value_unchecked(&"a").is_none());
+        assert!(c.tm.get_value_unchecked(&"b").is_some());
+
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(c.tm.get_value_unchecked(&"b").is_none());
+
+        cleaner.stop();
+        assert!(c.calls.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[tokio::test]
+    async fn stop() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(20));
+
+        let cleaner = spawn_cleaner(TokioSpawner, vec![tm.clone()], Duration::from_millis(10));
+        assert!(cleaner.is_running());
+
+        let state = cleaner.state.clone();
+        cleaner.stop();
+
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(!state.is_running());
+        assert!(tm.get_value_unchecked(&"a").is_some());
+    }
+
+    #[tokio::test]
+    async fn join_after_panic() {
+        struct Panicking;
+
+        impl Cleanup for Panicking {
+            fn cleanup(&self) -> usize {
+                panic!("cleanup failed");
+            }
+        }
+
+        let cleaner = spawn_cleaner(
+            TokioSpawner,
+            vec![Arc::new(Panicking)],
+            Duration::from_millis(1),
+        );
+        assert!(cleaner.is_running());
+
+        time::timeout(Duration::from_secs(1), cleaner.join())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_interval() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(10));
+
+        let cleaner = spawn_cleaner(TokioSpawner, vec![tm.clone()], Duration::from_secs(60));
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(tm.get_value_unchecked(&"a").is_some());
+
+        cleaner.set_interval(Duration::from_millis(10));
+        assert_eq!(cleaner.interval(), Duration::from_millis(10));
+
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(tm.get_value_unchecked(&"a").is_none());
+
+        cleaner.stop();
+    }
+
+    #[tokio::test]
+    async fn multi_cleanup() {
+        let a = Arc::new(TimedMap::new());
+        a.insert("a", 1, Duration::from_millis(50));
+        let b = Arc::new(TimedMap::new());
+        b.insert(1, "b", Duration::from_millis(100));
+
+        let cleaner = spawn_cleaner(
+            TokioSpawner,
+            vec![a.clone(), b.clone()],
+            Duration::from_millis(10),
+        );
+
+        let c = Arc::new(TimedMap::new());
+        c.insert('c', 3, Duration::from_millis(50));
+        cleaner.add(c.clone());
+
+        time::sleep(Duration::from_millis(75)).await;
+        assert!(a.get_value_unchecked(&"a").is_none());
+        assert!(b.get_value_unchecked(&1).is_some());
+        assert!(c.get_value_unchecked(&'c').is_none());
+
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(b.get_value_unchecked(&1).is_none());
+
+        cleaner.stop();
+    }
+
+    #[tokio::test]
+    async fn weak_cleanup() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(10));
+
+        let weak = Arc::downgrade(&tm);
+        let cleaner = crate::start_cleaner_weak(weak, Duration::from_millis(10));
+
+        time::sleep(Duration::from_millis(30)).await;
+        assert!(tm.get_value_unchecked(&"a").is_none());
+        assert!(cleaner.is_running());
+
+        drop(tm);
+        time::timeout(Duration::from_millis(30), cleaner.join())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn graceful_shutdown() {
+        #[derive(Default)]
+        struct Slow {
+            started: AtomicUsize,
+            finished: AtomicUsize,
+        }
+
+        impl Cleanup for Slow {
+            fn cleanup(&self) -> usize {
+                self.started.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(50));
+                self.finished.fetch_add(1, Ordering::SeqCst);
+                0
+            }
+        }
+
+        let slow = Arc::new(Slow::default());
+        let cleaner = spawn_cleaner(TokioSpawner, vec![slow.clone()], Duration::from_millis(1));
+        let state = cleaner.state.clone();
+
+        while slow.started.load(Ordering::SeqCst) == 0 {
+            time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(slow.finished.load(Ordering::SeqCst), 0);
+
+        time::timeout(Duration::from_secs(1), cleaner.shutdown())
+            .await
+            .unwrap();
+        assert!(!state.is_running());
+        assert_eq!(slow.started.load(Ordering::SeqCst), 1);
+        assert_eq!(slow.finished.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn async_cleanup() {
+        struct Mock {
+            tm: tokio::sync::Mutex<TimedMap<&'static str, i32>>,
+        }
+
+        impl crate::AsyncCleanup for Mock {
+            async fn cleanup(&self) -> usize {
+                self.tm.lock().await.cleanup()
+            }
+        }
+
+        let m = Arc::new(Mock {
+            tm: tokio::sync::Mutex::new(TimedMap::new()),
+        });
+        m.tm.lock().await.insert("a", 1, Duration::from_millis(20));
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("b", 2, Duration::from_millis(20));
+
+        let cleaner =
+            crate::start_async_cleaner_on(TokioSpawner, m.clone(), Duration::from_millis(10));
+        cleaner.add(tm.clone());
+
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(m.tm.lock().await.get_value_unchecked(&"a").is_none());
+        assert!(tm.get_value_unchecked(&"b").is_none());
+
+        cleaner.shutdown().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn slow_cleanup() {
+        struct Slow {
+            interval: Duration,
+            duration: Duration,
+            starts: std::sync::Mutex<Vec<std::time::Instant>>,
+        }
+
+        impl Cleanup for Slow {
+            fn cleanup(&self) -> usize {
+                self.starts.lock().unwrap().push(std::time::Instant::now());
+                std::thread::sleep(self.duration);
+                0
+            }
+        }
+
+        for (interval, duration, step) in [(50, 30, 50), (50, 70, 100)] {
+            let slow = Arc::new(Slow {
+                interval: Duration::from_millis(interval),
+                duration: Duration::from_millis(duration),
+                starts: Default::default(),
+            });
+            let cleaner = spawn_cleaner(TokioSpawner, vec![slow.clone()], slow.interval);
+            time::sleep(Duration::from_millis(interval + 6 * step)).await;
+            cleaner.shutdown().await;
+
+            let starts = slow.starts.lock().unwrap();
+            assert!(starts.len() >= 5, "{} passes", starts.len());
+            for (i, start) in starts.iter().enumerate() {
+                let offset = start.duration_since(starts[0]);
+                let expected = Duration::from_millis(step * i as u64);
+                assert!(
+                    offset.abs_diff(expected) < Duration::from_millis(20),
+                    "pass {i} started after {offset:?}, expected {expected:?}"
+                );
+            }
+        }
+    }
 }
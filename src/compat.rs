@@ -0,0 +1,39 @@
+//! Provides the hash map and the default type parameters used by the
+//! maps, depending on whether the `no-std` feature is enabled.
+//!
+//! By default, the hash map and [`RandomState`] of the standard
+//! library and [`Instant`] are used. With the `no-std` feature, the
+//! map of [`hashbrown`] is used instead, keys are hashed with
+//! [`SipHasher`] using fixed keys and the [`TimeSource`] has to be
+//! specified explicitly.
+//!
+//! [`RandomState`]: https://doc.rust-lang.org/std/hash/struct.RandomState.html
+//! [`Instant`]: https://doc.rust-lang.org/std/time/struct.Instant.html
+//! [`hashbrown`]: https://docs.rs/hashbrown
+//! [`SipHasher`]: core::hash::SipHasher
+//! [`TimeSource`]: crate::time::TimeSource
+
+#[cfg(not(feature = "no-std"))]
+pub(crate) use std::{
+    collections::{hash_map, hash_map::RandomState as DefaultHashBuilder, HashMap},
+    time::Instant as DefaultTimeSource,
+};
+
+#[cfg(feature = "no-std")]
+pub(crate) use hashbrown::{hash_map, HashMap};
+
+/// Hash builder used by default with the `no-std` feature.
+///
+/// As there is no source of randomness without `std`, the keys
+/// of the hasher are fixed, so it provides no protection against
+/// HashDoS attacks. Pass a custom hash builder where that matters.
+#[cfg(feature = "no-std")]
+#[allow(deprecated)]
+pub type DefaultHashBuilder = core::hash::BuildHasherDefault<core::hash::SipHasher>;
+
+/// Placeholder for the default [`TimeSource`](crate::time::TimeSource)
+/// with the `no-std` feature, where no clock is available, so the
+/// time source always has to be specified.
+#[cfg(feature = "no-std")]
+#[derive(Debug)]
+pub enum DefaultTimeSource {}
@@ -0,0 +1,261 @@
+use crate::{time::TimeSource, Cleanup, Value};
+use dashmap::DashMap;
+use std::{
+    borrow::Borrow,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Provides a hash map with expiring key-value pairs like
+/// [`TimedMap`](crate::TimedMap), which is backed by a
+/// [`DashMap`].
+///
+/// In contrast to [`TimedMap`](crate::TimedMap), there is no
+/// single lock around the whole map, so concurrent writers to
+/// different keys do not block each other.
+///
+/// # Example
+/// ```
+/// use timedmap::ConcurrentTimedMap;
+/// use std::time::Duration;
+///
+/// let tm = ConcurrentTimedMap::new();
+/// tm.insert("foo", "bar", Duration::from_secs(10));
+/// assert_eq!(tm.get(&"foo"), Some("bar"));
+/// ```
+#[derive(Debug)]
+pub struct ConcurrentTimedMap<K, V, TS = Instant>
+where
+    K: Eq + Hash,
+{
+    inner: DashMap<K, Value<V, TS>>,
+}
+
+impl<K, V> ConcurrentTimedMap<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Create a new instance of [`ConcurrentTimedMap`] with the
+    /// default [`TimeSource`] implementation [`Instant`].
+    pub fn new() -> Self {
+        Self::new_with_timesource()
+    }
+}
+
+impl<K, V, TS> ConcurrentTimedMap<K, V, TS>
+where
+    K: Eq + Hash,
+{
+    /// Create a new instance of [`ConcurrentTimedMap`] with a
+    /// custom [`TimeSource`] implementation.
+    pub fn new_with_timesource() -> Self {
+        Self {
+            inner: DashMap::new(),
+        }
+    }
+}
+
+impl<K, V, TS> ConcurrentTimedMap<K, V, TS>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+{
+    /// Add a new key-value pair to the map with the
+    /// given lifetime.
+    ///
+    /// Returns the previous value for the given key if there
+    /// was one and it has not been expired.
+    pub fn insert(&self, key: K, value: V, lifetime: Duration) -> Option<V> {
+        self.inner
+            .insert(key, Value::new(value, lifetime))
+            .and_then(|v| v.value_checked())
+    }
+
+    /// Returns a copy of the value corresponding to the
+    /// given key.
+    ///
+    /// [`None`] is returned when the values lifetime has
+    /// been passed.
+    ///
+    /// # Behavior
+    ///
+    /// If the key-value pair has expired and not been
+    /// cleaned up before, it will be removed from the
+    /// map on next retrival try.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.inner.get(key) {
+            Some(v) if !v.is_expired() => return Some(v.value().value()),
+            Some(_) => {}
+            None => return None,
+        }
+        self.inner.remove_if(key, |_, v| v.is_expired());
+        None
+    }
+
+    /// Returns `true` when the map contains a non-expired
+    /// value for the given key.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Removes the given key-value pair from the map and
+    /// returns the value if it was previously in the map
+    /// and is not expired.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.remove(key).and_then(|(_, v)| v.value_checked())
+    }
+
+    /// Returns the number of key-value pairs in the map
+    /// which have not been expired.
+    pub fn len(&self) -> usize {
+        self.inner.iter().filter(|v| !v.is_expired()).count()
+    }
+
+    /// Returns `true` when the map does not contain any
+    /// non-expired key-value pair.
+    pub fn is_empty(&self) -> bool {
+        self.inner.iter().all(|v| v.is_expired())
+    }
+
+    /// Clears the map, removing all key-value pairs.
+    pub fn clear(&self) {
+        self.inner.clear();
+    }
+}
+
+impl<K, V> Default for ConcurrentTimedMap<K, V>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, TS> Cleanup for ConcurrentTimedMap<K, V, TS>
+where
+    K: Eq + PartialEq + Hash + Clone + Send + Sync,
+    V: Clone + Send + Sync,
+    TS: TimeSource + Send + Sync,
+{
+    fn cleanup(&self) -> usize {
+        let now = TS::now();
+        let mut removed = 0;
+        self.inner.retain(|_, v| {
+            let expired = v.is_expired_at(&now);
+            removed += expired as usize;
+            !expired
+        });
+        removed
+    }
+
+    fn until_next_expiry(&self) -> Option<Duration> {
+        let now = TS::now();
+        self.inner
+            .iter()
+            .filter(|v| !v.is_expired_at(&now))
+            .filter_map(|v| v.expires().cloned())
+            .min()
+            .map(|e| e.saturating_duration_since(&now))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_instant::{Instant, MockClock};
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn get_checked() {
+        let tm: ConcurrentTimedMap<_, _, Instant> = ConcurrentTimedMap::new_with_timesource();
+        assert!(tm.is_empty());
+
+        assert_eq!(tm.insert("a", 1, Duration::from_millis(10)), None);
+        assert_eq!(tm.insert("a", 2, Duration::from_millis(10)), Some(1));
+        assert_eq!(tm.len(), 1);
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.get(&"a"), Some(2));
+        assert!(tm.contains(&"a"));
+
+        MockClock::advance(Duration::from_millis(1));
+        assert_eq!(tm.get(&"a"), None);
+        assert!(tm.inner.get(&"a").is_none());
+        assert!(tm.is_empty());
+
+        tm.insert("b", 3, Duration::from_millis(10));
+        assert_eq!(tm.remove(&"b"), Some(3));
+        assert_eq!(tm.remove(&"b"), None);
+    }
+
+    #[test]
+    fn cleanup() {
+        let tm: ConcurrentTimedMap<_, _, Instant> = ConcurrentTimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(5));
+        tm.insert("b", 2, Duration::from_millis(10));
+        assert_eq!(tm.cleanup(), 0);
+
+        MockClock::advance(Duration::from_millis(6));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(tm.contains(&"b"));
+
+        MockClock::advance(Duration::from_millis(5));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn concurrent_inserts() {
+        let tm = Arc::new(ConcurrentTimedMap::new());
+
+        let handles: Vec<_> = (0..8u64)
+            .map(|t| {
+                let tm = tm.clone();
+                thread::spawn(move || {
+                    for i in 0..1_000 {
+                        let key = t * 1_000 + i;
+                        tm.insert(key, key, Duration::from_secs(60));
+                        assert_eq!(tm.get(&key), Some(key));
+                        if i % 2 == 1 {
+                            assert_eq!(tm.remove(&key), Some(key));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert_eq!(tm.len(), 4_000);
+        assert_eq!(tm.cleanup(), 0);
+    }
+
+    #[test]
+    fn until_next_expiry() {
+        let tm: ConcurrentTimedMap<_, _, Instant> = ConcurrentTimedMap::new_with_timesource();
+        assert_eq!(tm.until_next_expiry(), None);
+
+        tm.insert("a", 1, Duration::from_millis(50));
+        tm.insert("b", 2, Duration::from_millis(20));
+        assert_eq!(tm.until_next_expiry(), Some(Duration::from_millis(20)));
+
+        MockClock::advance(Duration::from_millis(30));
+        assert_eq!(tm.until_next_expiry(), Some(Duration::from_millis(20)));
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    compat::{DefaultHashBuilder, HashMap},
+    lock::RwLockWriteGuard,
+    time::TimeSource,
+    TimedMap, Value,
+};
+use core::{
+    hash::{BuildHasher, Hash},
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+type Lock<'a, K, V, TS, S> = RwLockWriteGuard<'a, HashMap<K, Value<V, TS>, S>>;
+type Evicted<K, V, TS> = (K, Value<V, TS>);
+type ReportFn<K, V, TS, S> = fn(&TimedMap<K, V, TS, S>, Evicted<K, V, TS>);
+
+/// Write lock of an entry, which passes an expired key-value pair
+/// removed on creation of the entry to the eviction function and
+/// the expiration channel of the map once the lock is released.
+struct Guard<'a, K, V, TS, S> {
+    map: &'a TimedMap<K, V, TS, S>,
+    guard: Option<Lock<'a, K, V, TS, S>>,
+    evicted: Option<Evicted<K, V, TS>>,
+    /// Reports `evicted` to the map. Stored as a function, as the
+    /// [`Drop`] implementation can not require the bounds needed
+    /// to call [`TimedMap::evicted`] directly.
+    report: ReportFn<K, V, TS, S>,
+}
+
+impl<K, V, TS, S> Deref for Guard<'_, K, V, TS, S> {
+    type Target = HashMap<K, Value<V, TS>, S>;
+
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_ref().expect("lock is held")
+    }
+}
+
+impl<K, V, TS, S> DerefMut for Guard<'_, K, V, TS, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.guard.as_mut().expect("lock is held")
+    }
+}
+
+impl<K, V, TS, S> Drop for Guard<'_, K, V, TS, S> {
+    fn drop(&mut self) {
+        self.guard = None;
+        if let Some(evicted) = self.evicted.take() {
+            (self.report)(self.map, evicted);
+        }
+    }
+}
+
+/// A view into a single entry of a [`TimedMap`](crate::TimedMap),
+/// which may either be vacant or occupied.
+///
+/// The entry holds the maps write lock until it is dropped, so
+/// all operations on it are atomic in respect to other accesses
+/// to the map.
+///
+/// This is constructed from the [`entry`](crate::TimedMap::entry)
+/// method on [`TimedMap`](crate::TimedMap).
+pub enum Entry<'a, K, V, TS, S = DefaultHashBuilder> {
+    /// An entry with a non-expired value.
+    Occupied(OccupiedEntry<'a, K, V, TS, S>),
+    /// An entry without a value or with an expired value.
+    Vacant(VacantEntry<'a, K, V, TS, S>),
+}
+
+/// A view into an occupied entry of a [`TimedMap`](crate::TimedMap).
+///
+/// It is part of the [`Entry`] enum.
+pub struct OccupiedEntry<'a, K, V, TS, S = DefaultHashBuilder> {
+    guard: Guard<'a, K, V, TS, S>,
+    key: K,
+}
+
+/// A view into a vacant entry of a [`TimedMap`](crate::TimedMap).
+///
+/// It is part of the [`Entry`] enum.
+pub struct VacantEntry<'a, K, V, TS, S = DefaultHashBuilder> {
+    map: &'a TimedMap<K, V, TS, S>,
+    guard: Guard<'a, K, V, TS, S>,
+    key: K,
+}
+
+impl<'a, K, V, TS, S> Entry<'a, K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    pub(crate) fn new(map: &'a TimedMap<K, V, TS, S>, key: K) -> Self {
+        let mut guard = Guard {
+            map,
+            guard: Some(map.inner.write()),
+            evicted: None,
+            report: |map, evicted| map.evicted(Some(evicted)),
+        };
+        match guard.get_mut(&key) {
+            Some(v) if !map.is_expired(v) => {
+                map.mark_used(v);
+                if let Some(lifetime) = map.sliding_lifetime().filter(|_| !v.is_permanent()) {
+                    v.set_expiry(lifetime);
+                }
+                Entry::Occupied(OccupiedEntry { guard, key })
+            }
+            Some(_) => {
+                guard.evicted = guard.remove_entry(&key);
+                Entry::Vacant(VacantEntry { map, guard, key })
+            }
+            None => Entry::Vacant(VacantEntry { map, guard, key }),
+        }
+    }
+
+    /// Returns a reference to the entries key.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the given
+    /// value with the given lifetime if the entry is vacant
+    /// and returns the occupied entry.
+    pub fn or_insert(self, value: V, lifetime: Duration) -> OccupiedEntry<'a, K, V, TS, S> {
+        match self {
+            Entry::Occupied(e) => e,
+            Entry::Vacant(e) => e.insert(value, lifetime),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result
+    /// of the given function with the given lifetime if the entry
+    /// is vacant and returns the occupied entry.
+    ///
+    /// The function is only called when the entry is vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(
+        self,
+        f: F,
+        lifetime: Duration,
+    ) -> OccupiedEntry<'a, K, V, TS, S> {
+        match self {
+            Entry::Occupied(e) => e,
+            Entry::Vacant(e) => e.insert(f(), lifetime),
+        }
+    }
+}
+
+impl<K, V, TS, S> OccupiedEntry<'_, K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    /// Returns a reference to the entries key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns a reference to the entries value.
+    pub fn get(&self) -> &V {
+        self.value().value_ref()
+    }
+
+    /// Returns a mutable reference to the entries value.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.value_mut().value_mut()
+    }
+
+    /// Replaces the entries value with the given value and
+    /// returns the previous value.
+    ///
+    /// The expiry of the entry stays unchanged.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the map and returns its value.
+    pub fn remove(mut self) -> V {
+        self.guard.remove(&self.key).expect("entry exists").value()
+    }
+
+    fn value(&self) -> &Value<V, TS> {
+        self.guard.get(&self.key).expect("entry exists")
+    }
+
+    fn value_mut(&mut self) -> &mut Value<V, TS> {
+        self.guard.get_mut(&self.key).expect("entry exists")
+    }
+}
+
+impl<'a, K, V, TS, S> VacantEntry<'a, K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    /// Returns a reference to the entries key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Sets the value of the entry with the given lifetime
+    /// and returns the occupied entry.
+    ///
+    /// If the map has a maximum capacity which would be exceeded,
+    /// the least recently used key-value pair is evicted first.
+    pub fn insert(mut self, value: V, lifetime: Duration) -> OccupiedEntry<'a, K, V, TS, S> {
+        self.map.insert_value_locked(
+            &mut self.guard,
+            self.key.clone(),
+            Value::new(value, lifetime),
+        );
+        OccupiedEntry {
+            guard: self.guard,
+            key: self.key,
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use core::fmt;
+
+/// Error returned from [`try_insert`](crate::TimedMap::try_insert)
+/// when a key-value pair is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InsertError {
+    /// The given lifetime is zero, so the key-value pair
+    /// would expire immediately.
+    ZeroLifetime,
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroLifetime => f.write_str("lifetime must not be zero"),
+        }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl std::error::Error for InsertError {}
@@ -0,0 +1,153 @@
+use crate::time::TimeSource;
+use core::time::Duration;
+
+/// The expiry of a [`Value`](crate::Value), which is either
+/// an absolute deadline or never.
+///
+/// It converts between the deadline and the remaining lifetime
+/// relative to a given time.
+///
+/// # Example
+/// ```
+/// use timedmap::{Expiry, TimedMap};
+/// use std::time::{Duration, Instant};
+///
+/// let tm = TimedMap::new();
+/// let deadline = Instant::now() + Duration::from_secs(60);
+/// tm.insert_at("foo", "bar", deadline);
+///
+/// let expiry = tm.get_value(&"foo").unwrap().expiry();
+/// assert_eq!(expiry, Expiry::At(deadline));
+/// assert!(expiry.remaining().unwrap() <= Duration::from_secs(60));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Expiry<TS> {
+    /// The value never expires.
+    Never,
+    /// The value expires after the given deadline.
+    At(TS),
+}
+
+impl<TS: TimeSource> Expiry<TS> {
+    /// Returns the expiry which is reached after the given
+    /// lifetime from now.
+    ///
+    /// If the deadline can not be represented by the time
+    /// source, the expiry is [`Expiry::Never`].
+    pub fn after(lifetime: Duration) -> Self {
+        Self::after_at(lifetime, &TS::now())
+    }
+
+    /// Returns the expiry which is reached after the given
+    /// lifetime from `now`.
+    ///
+    /// If the deadline can not be represented by the time
+    /// source, the expiry is [`Expiry::Never`].
+    pub fn after_at(lifetime: Duration, now: &TS) -> Self {
+        now.checked_add(lifetime).into()
+    }
+
+    /// Returns the deadline or [`None`] if the value never
+    /// expires.
+    pub fn deadline(&self) -> Option<&TS> {
+        match self {
+            Self::Never => None,
+            Self::At(deadline) => Some(deadline),
+        }
+    }
+
+    /// Returns the remaining lifetime from now or [`None`]
+    /// if the value never expires.
+    ///
+    /// Returns [`Duration::ZERO`] if the deadline has passed.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.remaining_at(&TS::now())
+    }
+
+    /// Returns the remaining lifetime from `now` or [`None`]
+    /// if the value never expires.
+    ///
+    /// Returns [`Duration::ZERO`] if the deadline is before `now`.
+    pub fn remaining_at(&self, now: &TS) -> Option<Duration> {
+        self.deadline()
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+}
+
+impl<TS> From<Option<TS>> for Expiry<TS> {
+    fn from(deadline: Option<TS>) -> Self {
+        match deadline {
+            Some(deadline) => Self::At(deadline),
+            None => Self::Never,
+        }
+    }
+}
+
+impl<TS> From<Expiry<TS>> for Option<TS> {
+    fn from(expiry: Expiry<TS>) -> Self {
+        match expiry {
+            Expiry::Never => None,
+            Expiry::At(deadline) => Some(deadline),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TimedMap, Value};
+    use mock_instant::{Instant, MockClock};
+
+    #[test]
+    fn deadline_and_remaining() {
+        let now = Instant::now();
+        let expiry = Expiry::after(Duration::from_millis(100));
+        assert_eq!(expiry.deadline(), Some(&(now + Duration::from_millis(100))));
+        assert_eq!(expiry.remaining(), Some(Duration::from_millis(100)));
+
+        MockClock::advance(Duration::from_millis(40));
+        assert_eq!(expiry.remaining(), Some(Duration::from_millis(60)));
+        assert_eq!(
+            expiry.remaining_at(&(now + Duration::from_millis(90))),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            Expiry::after_at(Duration::from_millis(60), &Instant::now()),
+            expiry
+        );
+
+        MockClock::advance(Duration::from_millis(100));
+        assert_eq!(expiry.remaining(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn never() {
+        let expiry = Expiry::<Instant>::Never;
+        assert_eq!(expiry.deadline(), None);
+        assert_eq!(expiry.remaining(), None);
+        assert_eq!(Option::<Instant>::from(expiry), None);
+        assert_eq!(Expiry::from(None::<Instant>), expiry);
+    }
+
+    #[test]
+    fn value_expiry() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        tm.insert_at("a", 1, deadline);
+        tm.insert_permanent("b", 2);
+
+        let v = tm.get_value(&"a").unwrap();
+        assert_eq!(v.expiry(), Expiry::At(deadline));
+        assert_eq!(
+            v.remaining_at(&Instant::now()),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(tm.get_value(&"b").unwrap().expiry(), Expiry::Never);
+
+        MockClock::advance(Duration::from_millis(20));
+        let remaining = v.remaining().unwrap();
+        assert_eq!(remaining, Duration::from_millis(30));
+        let v: Value<_, Instant> = Value::new(1, remaining);
+        assert_eq!(v.expiry(), Expiry::At(deadline));
+    }
+}
@@ -31,13 +31,15 @@
 //! expired key-value pairs in given time intervals using popular
 //! async runtimes.
 //!
-//! > Currently, only implementations for `tokio` and `actix-rt`
-//! are available. Implentations for other popular runtimes are
-//! planned in the future. If you want to contribute an implementation,
-//! feel free to create a
-//! [pull request](https://github.com/zekroTJA/timedmap-rs). 😄
+//! > Currently, only implementations for `tokio`, `actix-rt`,
+//! > `async-std` and `smol` are available. Implentations for other popular runtimes are
+//! > planned in the future. If you want to contribute an implementation,
+//! > feel free to create a
+//! > [pull request](https://github.com/zekroTJA/timedmap-rs). 😄
 //!
 //! ```
+//! # #[cfg(feature = "tokio")]
+//! # {
 //! use timedmap::{TimedMap, start_cleaner};
 //! use std::time::Duration;
 //! use std::sync::Arc;
@@ -45,21 +47,126 @@
 //! let tm = Arc::new(TimedMap::new());
 //! tm.insert("foo", 1, Duration::from_secs(60));
 //!
-//! # #[cfg(feature = "tokio")]
 //! # tokio_test::block_on(async {
-//! let cancel = start_cleaner(tm, Duration::from_secs(10));
+//! let cleaner = start_cleaner(tm, Duration::from_secs(10));
 //!
-//! cancel();
+//! cleaner.stop();
 //! # });
+//! # }
 //! ```
+//!
+//! If you don't use an async runtime, you can use `start_thread_cleaner`
+//! instead, which runs the cleanup cycle on a separate thread.
+//!
+//! # WebAssembly
+//!
+//! On `wasm32-unknown-unknown`, [`Instant`](std::time::Instant) is
+//! not available. Enable the `wasm` feature and use
+//! `TimedMap<K, V, time::WebTime>` on this target instead.
+//!
+//! # `no_std`
+//!
+//! With the `no-std` feature, the crate is `#![no_std]` and only
+//! requires an allocator. As there is no clock, the [`TimeSource`]
+//! has to be passed explicitly, e.g. via
+//! `TimedMap::<K, V, MyClock>::new_with_timesource()`. Keys are hashed
+//! with fixed keys by default, the expiration channel and the cleanup
+//! cycles are not available and the other optional features can not
+//! be combined with `no-std`.
+//!
+//! # Logging
+//!
+//! With the `log` feature, inserts, lazy evictions and cleanup runs
+//! are logged via the [`log`](https://docs.rs/log) crate with the
+//! target `timedmap`. The events carry the number of removed
+//! key-value pairs and the length of the map as key-value fields.
+//!
+//! A native `tracing` feature with spans has been requested, but is
+//! blocked for now, as `tracing` can not be added as a dependency
+//! yet. The `log` feature is a stand-in until then: `tracing`
+//! subscribers receive its events via `tracing-log`, but no spans
+//! are emitted.
+//!
+//! [`TimeSource`]: time::TimeSource
+
+#![cfg_attr(feature = "no-std", no_std)]
+
+extern crate alloc;
+
+#[cfg(all(
+    feature = "no-std",
+    any(
+        feature = "actix-rt",
+        feature = "async-std",
+        feature = "dashmap",
+        feature = "parking-lot",
+        feature = "persist",
+        feature = "serde",
+        feature = "smol",
+        feature = "timer-wheel",
+        feature = "tokio",
+        feature = "wasm"
+    )
+))]
+compile_error!("the `no-std` feature can not be combined with other features");
+
+#[macro_use]
+mod logging;
+
+mod compat;
+
+mod lock;
 
 mod timedmap;
 pub use crate::timedmap::*;
 
+mod builder;
+pub use crate::builder::*;
+
 mod value;
 pub use crate::value::*;
 
+mod expiry;
+pub use crate::expiry::*;
+
+mod entry;
+pub use crate::entry::*;
+
+mod value_ref;
+pub use crate::value_ref::*;
+
+mod read_guard;
+pub use crate::read_guard::*;
+
+mod set;
+pub use crate::set::*;
+
+mod sharded;
+pub use crate::sharded::*;
+
 mod cleanup;
 pub use crate::cleanup::*;
 
+mod stats;
+pub use crate::stats::*;
+
+mod error;
+pub use crate::error::*;
+
+#[cfg(feature = "serde")]
+mod serde_impl;
+
+#[cfg(feature = "persist")]
+mod persist;
+
+#[cfg(feature = "dashmap")]
+mod concurrent;
+#[cfg(feature = "dashmap")]
+pub use crate::concurrent::*;
+
+#[cfg(feature = "timer-wheel")]
+mod wheel;
+#[cfg(feature = "timer-wheel")]
+pub use crate::wheel::*;
+
 pub mod time;
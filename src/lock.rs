@@ -0,0 +1,105 @@
+//! Provides the [`RwLock`] implementation used by the maps.
+//!
+//! By default, this wraps [`std::sync::RwLock`] and recovers the
+//! guard when the lock has been poisoned by a thread panicking while
+//! holding it, so a single panic does not render the map unusable.
+//! With the `parking-lot` feature enabled, [`parking_lot::RwLock`]
+//! is used instead, which does not poison at all.
+//!
+//! With the `no-std` feature, a spinning reader-writer lock built on
+//! [`lock_api`] is used, as no operating system locks are available.
+
+#[cfg(feature = "parking-lot")]
+pub(crate) use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(not(any(feature = "parking-lot", feature = "no-std")))]
+pub(crate) use std::sync::{PoisonError, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(not(any(feature = "parking-lot", feature = "no-std")))]
+#[derive(Debug, Default)]
+pub(crate) struct RwLock<T>(std::sync::RwLock<T>);
+
+#[cfg(not(any(feature = "parking-lot", feature = "no-std")))]
+impl<T> RwLock<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self(std::sync::RwLock::new(value))
+    }
+
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(feature = "no-std")]
+pub(crate) type RwLock<T> = lock_api::RwLock<RawSpinRwLock, T>;
+#[cfg(feature = "no-std")]
+pub(crate) type RwLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, RawSpinRwLock, T>;
+#[cfg(feature = "no-std")]
+pub(crate) type RwLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, RawSpinRwLock, T>;
+
+/// A reader-writer lock which spins while waiting for the lock.
+///
+/// The state holds the number of readers, or [`WRITER`] while the
+/// lock is held exclusively.
+#[cfg(feature = "no-std")]
+pub(crate) struct RawSpinRwLock(core::sync::atomic::AtomicUsize);
+
+#[cfg(feature = "no-std")]
+const WRITER: usize = usize::MAX;
+
+#[cfg(feature = "no-std")]
+unsafe impl lock_api::RawRwLock for RawSpinRwLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self(core::sync::atomic::AtomicUsize::new(0));
+
+    type GuardMarker = lock_api::GuardSend;
+
+    fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        use core::sync::atomic::Ordering;
+        let state = self.0.load(Ordering::Relaxed);
+        state < WRITER - 1
+            && self
+                .0
+                .compare_exchange(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.0.fetch_sub(1, core::sync::atomic::Ordering::Release);
+    }
+
+    fn lock_exclusive(&self) {
+        while !self.try_lock_exclusive() {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        use core::sync::atomic::Ordering;
+        self.0
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.0.store(0, core::sync::atomic::Ordering::Release);
+    }
+
+    fn is_locked(&self) -> bool {
+        self.0.load(core::sync::atomic::Ordering::Relaxed) != 0
+    }
+}
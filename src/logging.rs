@@ -0,0 +1,153 @@
+//! Log events emitted with the `log` feature.
+//!
+//! This stands in for the requested `tracing` feature, which is
+//! blocked as `tracing` can not be added as a dependency yet. The
+//! events are plain `log` records, so `tracing` subscribers only
+//! receive them via `tracing-log` and there are no spans.
+//!
+//! Without the feature, the macros expand to nothing, so neither
+//! the arguments are evaluated nor any code is generated.
+
+/// Emits a trace event with the target `timedmap`.
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::trace!(target: "timedmap", $($arg)+);
+    };
+}
+
+/// Emits a debug event with the target `timedmap`.
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::debug!(target: "timedmap", $($arg)+);
+    };
+}
+
+/// Emits a warning event with the target `timedmap`.
+#[allow(unused_macros)]
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::warn!(target: "timedmap", $($arg)+);
+    };
+}
+
+#[cfg(all(test, feature = "log"))]
+mod test {
+    use crate::{Cleanup, TimedMap};
+    use log::{
+        kv::{Key, Value, VisitSource},
+        Level, Log, Metadata, Record,
+    };
+    use std::{
+        collections::HashMap,
+        sync::{Mutex, Once},
+        thread::{self, ThreadId},
+        time::Duration,
+    };
+
+    type Event = (ThreadId, Level, String, HashMap<String, String>);
+
+    static EVENTS: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+
+    struct Collector;
+
+    impl Log for Collector {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == "timedmap"
+        }
+
+        fn log(&self, record: &Record) {
+            struct Fields(HashMap<String, String>);
+
+            impl<'kvs> VisitSource<'kvs> for Fields {
+                fn visit_pair(
+                    &mut self,
+                    k: Key<'kvs>,
+                    v: Value<'kvs>,
+                ) -> Result<(), log::kv::Error> {
+                    self.0.insert(k.to_string(), v.to_string());
+                    Ok(())
+                }
+            }
+
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            let mut fields = Fields(HashMap::new());
+            record.key_values().visit(&mut fields).unwrap();
+            EVENTS.lock().unwrap().push((
+                thread::current().id(),
+                record.level(),
+                record.args().to_string(),
+                fields.0,
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Returns all events emitted on the current thread so far.
+    fn events() -> Vec<(Level, String, HashMap<String, String>)> {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&Collector).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+
+        let id = thread::current().id();
+        EVENTS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(t, ..)| *t == id)
+            .map(|(_, level, msg, fields)| (*level, msg.clone(), fields.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn cleanup() {
+        events();
+        let tm = TimedMap::new();
+        tm.insert("a", 1, Duration::ZERO);
+        tm.insert("b", 2, Duration::ZERO);
+        tm.insert("c", 3, Duration::from_secs(60));
+        thread::sleep(Duration::from_millis(1));
+        assert_eq!(tm.cleanup(), 2);
+
+        let events = events();
+        assert_eq!(
+            events
+                .iter()
+                .filter(|(_, msg, _)| msg == "inserted")
+                .count(),
+            3
+        );
+        let (level, _, fields) = events
+            .iter()
+            .find(|(_, msg, _)| msg == "cleaned up")
+            .unwrap();
+        assert_eq!(*level, Level::Debug);
+        assert_eq!(fields["removed"], "2");
+        assert_eq!(fields["len"], "1");
+        assert!(fields.contains_key("elapsed"));
+    }
+
+    #[test]
+    fn lazy_eviction() {
+        events();
+        let tm = TimedMap::new();
+        tm.insert("a", 1, Duration::ZERO);
+        thread::sleep(Duration::from_millis(1));
+        assert_eq!(tm.get(&"a"), None);
+
+        let events = events();
+        let (level, _, fields) = events
+            .iter()
+            .find(|(_, msg, _)| msg == "evicted expired value")
+            .unwrap();
+        assert_eq!(*level, Level::Trace);
+        assert_eq!(fields["len"], "0");
+    }
+}
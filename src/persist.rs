@@ -0,0 +1,136 @@
+use crate::{time::TimeSource, TimedMap, Value};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    hash::{BuildHasher, Hash},
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+impl<K, V, TS, S> TimedMap<K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    /// Writes all non-expired key-value pairs of the map with
+    /// their remaining lifetimes to the given writer, so that the
+    /// map can be restored via [`load`](#method.load).
+    ///
+    /// The key-value pairs are encoded as [CBOR](https://cbor.io)
+    /// with the remaining lifetimes in milliseconds. The read lock
+    /// of the map is held while writing.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::{io::Cursor, time::Duration};
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo".to_string(), 1, Duration::from_secs(60));
+    ///
+    /// let mut buf = Vec::new();
+    /// tm.dump(&mut buf).unwrap();
+    ///
+    /// let tm: TimedMap<String, i32> = TimedMap::load(Cursor::new(buf)).unwrap();
+    /// assert_eq!(tm.get("foo"), Some(1));
+    /// ```
+    pub fn dump<W: Write>(&self, w: W) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let now = TS::now();
+        let m = self.inner.read();
+        let entries: Vec<_> = m
+            .iter()
+            .filter(|(_, v)| !self.is_expired_at(v, &now))
+            .map(|(k, v)| {
+                let remaining = v
+                    .expires()
+                    .map(|e| e.saturating_duration_since(&now).as_millis())
+                    .map(|ms| u64::try_from(ms).unwrap_or(u64::MAX));
+                (k, v.value_ref(), remaining)
+            })
+            .collect();
+
+        ciborium::into_writer(&entries, w).map_err(|err| match err {
+            ciborium::ser::Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        })
+    }
+
+    /// Reads a map written via [`dump`](#method.dump) from
+    /// the given reader.
+    ///
+    /// The expiry of each key-value pair is calculated from its
+    /// remaining lifetime and the current time. Key-value pairs
+    /// without remaining lifetime are dropped, key-value pairs
+    /// without expiry never expire.
+    pub fn load<R: Read>(r: R) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        S: Default,
+    {
+        let entries: Vec<(K, V, Option<u64>)> =
+            ciborium::from_reader(r).map_err(|err| match err {
+                ciborium::de::Error::Io(err) => err,
+                err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+            })?;
+
+        let tm = Self::new_with_timesource_and_hasher(S::default());
+        {
+            let mut m = tm.inner.write();
+            for (k, v, remaining) in entries {
+                let v = match remaining {
+                    Some(0) => continue,
+                    Some(ms) => Value::new(v, Duration::from_millis(ms)),
+                    None => Value::new_permanent(v),
+                };
+                m.insert(k, v);
+            }
+        }
+        Ok(tm)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_instant::{Instant, MockClock};
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a".to_string(), 1, Duration::from_millis(100));
+        tm.insert("b".to_string(), 2, Duration::from_millis(300));
+        tm.insert("c".to_string(), 3, Duration::from_millis(10));
+        tm.insert_permanent("d".to_string(), 4);
+
+        MockClock::advance(Duration::from_millis(50));
+        let mut buf = Cursor::new(Vec::new());
+        tm.dump(&mut buf).unwrap();
+
+        MockClock::advance(Duration::from_secs(10));
+        buf.set_position(0);
+        let tm: TimedMap<String, i32, Instant> = TimedMap::load(&mut buf).unwrap();
+        assert_eq!(tm.len(), 3);
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(50)));
+        assert_eq!(tm.ttl("b"), Some(Duration::from_millis(250)));
+        assert_eq!(tm.get("c"), None);
+        assert_eq!(tm.get("d"), Some(4));
+        assert_eq!(tm.ttl("d"), None);
+
+        MockClock::advance(Duration::from_millis(51));
+        assert_eq!(tm.get("a"), None);
+        assert_eq!(tm.get("b"), Some(2));
+    }
+
+    #[test]
+    fn load_invalid() {
+        let err = TimedMap::<String, i32, Instant>::load(Cursor::new([0xff, 0x00])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
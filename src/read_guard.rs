@@ -0,0 +1,132 @@
+use crate::{
+    compat::{hash_map, DefaultHashBuilder, HashMap},
+    lock::RwLockReadGuard,
+    time::TimeSource,
+    Value,
+};
+use core::{
+    borrow::Borrow,
+    hash::{BuildHasher, Hash},
+};
+
+type Guard<'a, K, V, TS, S> = RwLockReadGuard<'a, HashMap<K, Value<V, TS>, S>>;
+
+/// A read-only view of all non-expired key-value pairs of a
+/// [`TimedMap`](crate::TimedMap).
+///
+/// The view holds the maps read lock until it is dropped, so the
+/// key-value pairs can be scanned without cloning them. Writes to
+/// the map are blocked as long as the view is alive.
+///
+/// Whether a key-value pair has expired is checked against the
+/// time at which the view has been created, so the view does not
+/// change while it is alive.
+///
+/// This is constructed from the [`read`](crate::TimedMap::read)
+/// method on [`TimedMap`](crate::TimedMap).
+pub struct MapReadGuard<'a, K, V, TS, S = DefaultHashBuilder> {
+    guard: Guard<'a, K, V, TS, S>,
+    now: TS,
+    inclusive: bool,
+}
+
+impl<'a, K, V, TS, S> MapReadGuard<'a, K, V, TS, S>
+where
+    K: Eq + Hash,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    pub(crate) fn new(guard: Guard<'a, K, V, TS, S>, now: TS, inclusive: bool) -> Self {
+        Self {
+            guard,
+            now,
+            inclusive,
+        }
+    }
+
+    /// Returns an iterator over references to all non-expired
+    /// key-value pairs in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V, TS> {
+        Iter {
+            inner: self.guard.iter(),
+            now: &self.now,
+            inclusive: self.inclusive,
+        }
+    }
+
+    /// Returns a reference to the non-expired value corresponding
+    /// to the given key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.guard
+            .get(key)
+            .filter(|v| !v.is_expired_at_with(&self.now, self.inclusive))
+            .map(Value::value_ref)
+    }
+
+    /// Returns `true` when the map contains a non-expired
+    /// value for the given key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the number of non-expired key-value pairs.
+    ///
+    /// This checks the expiry of every key-value pair, so it
+    /// takes `O(n)` time.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` when the map does not contain any
+    /// non-expired key-value pair.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+impl<'b, K, V, TS, S> IntoIterator for &'b MapReadGuard<'_, K, V, TS, S>
+where
+    K: Eq + Hash,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    type Item = (&'b K, &'b V);
+    type IntoIter = Iter<'b, K, V, TS>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over references to the non-expired key-value pairs
+/// of a [`MapReadGuard`].
+pub struct Iter<'b, K, V, TS> {
+    inner: hash_map::Iter<'b, K, Value<V, TS>>,
+    now: &'b TS,
+    inclusive: bool,
+}
+
+impl<'b, K, V, TS> Iterator for Iter<'b, K, V, TS>
+where
+    V: Clone,
+    TS: TimeSource,
+{
+    type Item = (&'b K, &'b V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .by_ref()
+            .find(|(_, v)| !v.is_expired_at_with(self.now, self.inclusive))
+            .map(|(k, v)| (k, v.value_ref()))
+    }
+}
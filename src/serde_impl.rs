@@ -0,0 +1,218 @@
+use crate::{time::TimeSource, TimedMap, Value};
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    fmt,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    time::Duration,
+};
+
+/// Portable representation of a [`Value`] which stores
+/// the remaining lifetime instead of the absolute expiry.
+#[derive(Serialize)]
+#[serde(rename = "Value")]
+struct SerValue<'a, V> {
+    value: &'a V,
+    remaining: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Value")]
+struct DeValue<V> {
+    value: V,
+    remaining: Option<Duration>,
+}
+
+impl<V, TS> From<DeValue<V>> for Value<V, TS>
+where
+    V: Clone,
+    TS: TimeSource,
+{
+    fn from(v: DeValue<V>) -> Self {
+        match v.remaining {
+            Some(remaining) => Value::new(v.value, remaining),
+            None => Value::new_permanent(v.value),
+        }
+    }
+}
+
+impl<V, TS> Serialize for Value<V, TS>
+where
+    V: Serialize + Clone,
+    TS: TimeSource,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerValue {
+            value: self.value_ref(),
+            remaining: self.remaining(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, V, TS> Deserialize<'de> for Value<V, TS>
+where
+    V: Deserialize<'de> + Clone,
+    TS: TimeSource,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        DeValue::deserialize(deserializer).map(Into::into)
+    }
+}
+
+impl<K, V, TS, S> Serialize for TimedMap<K, V, TS, S>
+where
+    K: Serialize + Eq + PartialEq + Hash + Clone,
+    V: Serialize + Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    /// Serializes all non-expired key-value pairs of the
+    /// map with their remaining lifetimes.
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let now = TS::now();
+        let m = self.inner.read();
+        let live: Vec<_> = m
+            .iter()
+            .filter(|(_, v)| !self.is_expired_at(v, &now))
+            .collect();
+
+        let mut map = serializer.serialize_map(Some(live.len()))?;
+        for (k, v) in live {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, K, V, TS, S> Deserialize<'de> for TimedMap<K, V, TS, S>
+where
+    K: Deserialize<'de> + Eq + PartialEq + Hash + Clone,
+    V: Deserialize<'de> + Clone,
+    TS: TimeSource,
+    S: BuildHasher + Default,
+{
+    /// Deserializes a map where the expiry of each key-value
+    /// pair is calculated from its remaining lifetime and the
+    /// current time.
+    ///
+    /// Key-value pairs without remaining lifetime are dropped,
+    /// key-value pairs without expiry never expire.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(TimedMapVisitor(PhantomData))
+    }
+}
+
+struct TimedMapVisitor<K, V, TS, S>(PhantomData<TimedMap<K, V, TS, S>>);
+
+impl<'de, K, V, TS, S> Visitor<'de> for TimedMapVisitor<K, V, TS, S>
+where
+    K: Deserialize<'de> + Eq + PartialEq + Hash + Clone,
+    V: Deserialize<'de> + Clone,
+    TS: TimeSource,
+    S: BuildHasher + Default,
+{
+    type Value = TimedMap<K, V, TS, S>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of keys to values with remaining lifetimes")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let tm = TimedMap::new_with_timesource_and_hasher(S::default());
+        {
+            let mut m = tm.inner.write();
+            while let Some((k, v)) = access.next_entry::<K, DeValue<V>>()? {
+                if v.remaining.is_some_and(|r| r.is_zero()) {
+                    continue;
+                }
+                m.insert(k, v.into());
+            }
+        }
+        Ok(tm)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_instant::{Instant, MockClock};
+
+    #[test]
+    fn value_roundtrip() {
+        let v: Value<_, Instant> = Value::new(1, Duration::from_millis(100));
+        MockClock::advance(Duration::from_millis(40));
+
+        let ser = serde_json::to_string(&v).unwrap();
+        MockClock::advance(Duration::from_millis(1000));
+
+        let v: Value<i32, Instant> = serde_json::from_str(&ser).unwrap();
+        assert_eq!(v.value(), 1);
+        assert_eq!(v.remaining(), Some(Duration::from_millis(60)));
+    }
+
+    #[test]
+    fn map_roundtrip() {
+        let tm: TimedMap<String, i32, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a".into(), 1, Duration::from_millis(10));
+        tm.insert("b".into(), 2, Duration::from_millis(20));
+        tm.insert("c".into(), 3, Duration::from_millis(30));
+
+        MockClock::advance(Duration::from_millis(11));
+        let ser = serde_json::to_string(&tm).unwrap();
+
+        MockClock::advance(Duration::from_millis(1000));
+        let tm: TimedMap<String, i32, Instant> = serde_json::from_str(&ser).unwrap();
+        assert_eq!(tm.len(), 2);
+        assert_eq!(tm.get("a"), None);
+        assert_eq!(tm.ttl("b"), Some(Duration::from_millis(9)));
+        assert_eq!(tm.ttl("c"), Some(Duration::from_millis(19)));
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.get("b"), None);
+        assert_eq!(tm.get("c"), Some(3));
+    }
+
+    #[test]
+    fn map_drops_zero_remaining() {
+        let ser = r#"{
+            "a": { "value": 1, "remaining": { "secs": 0, "nanos": 0 } },
+            "b": { "value": 2, "remaining": { "secs": 1, "nanos": 0 } }
+        }"#;
+
+        let tm: TimedMap<String, i32, Instant> = serde_json::from_str(ser).unwrap();
+        assert!(tm.get_value_unchecked("a").is_none());
+        assert_eq!(tm.get("b"), Some(2));
+    }
+
+    #[test]
+    fn map_roundtrip_permanent() {
+        let tm: TimedMap<String, i32, Instant> = TimedMap::new_with_timesource();
+        tm.insert_permanent("a".into(), 1);
+
+        let ser = serde_json::to_string(&tm).unwrap();
+        let tm: TimedMap<String, i32, Instant> = serde_json::from_str(&ser).unwrap();
+
+        MockClock::advance(Duration::from_secs(1_000_000));
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.ttl("a"), None);
+    }
+
+    #[test]
+    fn map_inclusive_deadline() {
+        let tm: TimedMap<String, i32, Instant> =
+            TimedMap::new_with_timesource().with_inclusive_deadline();
+        tm.insert("a".into(), 1, Duration::from_millis(10));
+        tm.insert("b".into(), 2, Duration::from_millis(20));
+
+        MockClock::advance(Duration::from_millis(10));
+        let ser = serde_json::to_string(&tm).unwrap();
+        let tm: TimedMap<String, i32, Instant> = serde_json::from_str(&ser).unwrap();
+        assert_eq!(tm.len_raw(), 1);
+        assert_eq!(tm.get("b"), Some(2));
+    }
+}
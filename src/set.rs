@@ -0,0 +1,160 @@
+use crate::{compat::DefaultTimeSource, time::TimeSource, Cleanup, TimedMap};
+use core::{borrow::Borrow, hash::Hash, time::Duration};
+
+/// Provides a hash set with expiring keys, backed
+/// by a [`TimedMap`] with `()` values.
+///
+/// # Example
+/// ```
+/// use timedmap::TimedSet;
+/// use std::time::Duration;
+///
+/// let seen = TimedSet::new();
+/// assert!(seen.insert("nonce", Duration::from_secs(60)));
+/// assert!(!seen.insert("nonce", Duration::from_secs(60)));
+/// assert!(seen.contains(&"nonce"));
+/// ```
+#[derive(Debug)]
+pub struct TimedSet<K, TS = DefaultTimeSource> {
+    inner: TimedMap<K, (), TS>,
+}
+
+#[cfg(not(feature = "no-std"))]
+impl<K> TimedSet<K> {
+    /// Create a new instance of [`TimedSet`] with the default
+    /// [`TimeSource`] implementation [`Instant`](std::time::Instant).
+    pub fn new() -> Self {
+        Self::new_with_timesource()
+    }
+}
+
+impl<K, TS> TimedSet<K, TS> {
+    /// Create a new instance of [`TimedSet`] with a custom
+    /// [`TimeSource`] implementation.
+    pub fn new_with_timesource() -> Self {
+        Self {
+            inner: TimedMap::new_with_timesource(),
+        }
+    }
+}
+
+impl<K, TS> TimedSet<K, TS>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    TS: TimeSource,
+{
+    /// Adds the given key to the set with the given lifetime.
+    ///
+    /// Returns `true` if the set did not contain the key
+    /// before. Otherwise, the lifetime of the existing key
+    /// stays unchanged.
+    pub fn insert(&self, key: K, lifetime: Duration) -> bool {
+        self.inner.insert_if_absent(key, (), lifetime)
+    }
+
+    /// Returns `true` when the set contains the given
+    /// key and it has not been expired.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.contains(key)
+    }
+
+    /// Removes the given key from the set and returns
+    /// `true` if it was contained and not expired.
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.remove(key).is_some()
+    }
+
+    /// Returns the remaining lifetime of the given key.
+    ///
+    /// [`None`] is returned when the key is not present
+    /// or has expired.
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.ttl(key)
+    }
+
+    /// Returns the number of keys in the set
+    /// which have not been expired.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns `true` when the set does not contain
+    /// any non-expired key.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Clears the set, removing all keys.
+    pub fn clear(&self) {
+        self.inner.clear();
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl<K> Default for TimedSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, TS> Cleanup for TimedSet<K, TS>
+where
+    K: Eq + PartialEq + Hash + Clone + Send + Sync,
+    TS: TimeSource + Send + Sync,
+{
+    fn cleanup(&self) -> usize {
+        self.inner.cleanup()
+    }
+
+    fn until_next_expiry(&self) -> Option<Duration> {
+        self.inner.until_next_expiry()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_instant::{Instant, MockClock};
+
+    #[test]
+    fn nonce_replay() {
+        let seen: TimedSet<_, Instant> = TimedSet::new_with_timesource();
+
+        assert!(seen.insert("n1", Duration::from_millis(100)));
+        assert!(seen.insert("n2", Duration::from_millis(100)));
+        assert!(!seen.insert("n1", Duration::from_millis(500)));
+        assert_eq!(seen.len(), 2);
+
+        MockClock::advance(Duration::from_millis(101));
+        assert!(!seen.contains("n1"));
+        assert!(seen.insert("n1", Duration::from_millis(100)));
+        assert_eq!(seen.cleanup(), 1);
+        assert_eq!(seen.len(), 1);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let blocked: TimedSet<_, Instant> = TimedSet::new_with_timesource();
+
+        blocked.insert("10.0.0.1", Duration::from_secs(10));
+        assert!(blocked.contains("10.0.0.1"));
+        assert!(!blocked.contains("10.0.0.2"));
+        assert_eq!(blocked.ttl("10.0.0.1"), Some(Duration::from_secs(10)));
+
+        assert!(blocked.remove("10.0.0.1"));
+        assert!(!blocked.remove("10.0.0.1"));
+        assert!(blocked.is_empty());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{
+    compat::{DefaultHashBuilder, DefaultTimeSource},
+    time::TimeSource,
+    Cleanup, TimedMap,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    borrow::Borrow,
+    hash::{BuildHasher, Hash},
+    time::Duration,
+};
+
+/// Number of shards used by [`ShardedTimedMap::new`].
+pub const DEFAULT_SHARD_COUNT: usize = 16;
+
+/// Provides a hash map with expiring key-value pairs like
+/// [`TimedMap`], which distributes its key-value pairs over
+/// multiple shards with a lock each.
+///
+/// Writers to keys in different shards do not block each other,
+/// which reduces lock contention without an external dependency.
+/// Operations on all key-value pairs, like [`len`](Self::len) or
+/// [`cleanup`](Cleanup::cleanup), lock one shard after another,
+/// so they do not see a consistent state of the whole map.
+///
+/// # Example
+/// ```
+/// use timedmap::ShardedTimedMap;
+/// use std::time::Duration;
+///
+/// let tm = ShardedTimedMap::new();
+/// tm.insert("foo", "bar", Duration::from_secs(10));
+/// assert_eq!(tm.get(&"foo"), Some("bar"));
+/// ```
+#[derive(Debug)]
+pub struct ShardedTimedMap<K, V, TS = DefaultTimeSource, S = DefaultHashBuilder> {
+    shards: Box<[TimedMap<K, V, TS, S>]>,
+    hasher: S,
+    /// Number of bits the hash is shifted by to get the shard index.
+    shift: u32,
+}
+
+#[cfg(not(feature = "no-std"))]
+impl<K, V> ShardedTimedMap<K, V> {
+    /// Create a new instance of [`ShardedTimedMap`] with the default
+    /// [`TimeSource`] implementation and [`DEFAULT_SHARD_COUNT`]
+    /// shards.
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARD_COUNT)
+    }
+
+    /// Create a new instance of [`ShardedTimedMap`] with the default
+    /// [`TimeSource`] implementation and the given number of shards,
+    /// which is rounded up to the next power of two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards(shards: usize) -> Self {
+        Self::new_with_timesource_and_hasher(shards, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, TS> ShardedTimedMap<K, V, TS> {
+    /// Create a new instance of [`ShardedTimedMap`] with a custom
+    /// [`TimeSource`] implementation and the given number of shards,
+    /// which is rounded up to the next power of two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new_with_timesource(shards: usize) -> Self {
+        Self::new_with_timesource_and_hasher(shards, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, TS, S: Clone> ShardedTimedMap<K, V, TS, S> {
+    /// Create a new instance of [`ShardedTimedMap`] with a custom
+    /// [`TimeSource`] implementation and the given number of shards,
+    /// which uses the given hash builder to hash keys.
+    ///
+    /// The number of shards is rounded up to the next power of two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new_with_timesource_and_hasher(shards: usize, hasher: S) -> Self {
+        assert!(shards > 0, "shard count must not be zero");
+        let shards = shards.next_power_of_two();
+        Self {
+            shards: (0..shards)
+                .map(|_| TimedMap::new_with_timesource_and_hasher(hasher.clone()))
+                .collect(),
+            hasher,
+            shift: u64::BITS - shards.trailing_zeros(),
+        }
+    }
+}
+
+impl<K, V, TS, S> ShardedTimedMap<K, V, TS, S> {
+    /// Returns the number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+}
+
+impl<K, V, TS, S> ShardedTimedMap<K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    /// Add a new key-value pair to the map with the
+    /// given lifetime.
+    ///
+    /// Returns the previous value for the given key if there
+    /// was one and it has not been expired.
+    pub fn insert(&self, key: K, value: V, lifetime: Duration) -> Option<V> {
+        self.shard(&key).insert(key, value, lifetime)
+    }
+
+    /// Returns a copy of the value corresponding to the
+    /// given key.
+    ///
+    /// [`None`] is returned when the values lifetime has
+    /// been passed.
+    ///
+    /// # Behavior
+    ///
+    /// If the key-value pair has expired and not been
+    /// cleaned up before, it will be removed from the
+    /// map on next retrival try.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).get(key)
+    }
+
+    /// Returns `true` when the map contains a non-expired
+    /// value for the given key.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).contains(key)
+    }
+
+    /// Removes the given key-value pair from the map and
+    /// returns the value if it was previously in the map
+    /// and is not expired.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).remove(key)
+    }
+
+    /// Returns the number of key-value pairs in the map
+    /// which have not been expired.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(TimedMap::len).sum()
+    }
+
+    /// Returns `true` when the map does not contain any
+    /// non-expired key-value pair.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(TimedMap::is_empty)
+    }
+
+    /// Clears the map, removing all key-value pairs.
+    pub fn clear(&self) {
+        self.shards.iter().for_each(TimedMap::clear);
+    }
+
+    /// Create a snapshot of the current state of the maps
+    /// key-value entries.
+    ///
+    /// It does only contain all non-expired key-value pairs.
+    pub fn snapshot<B: FromIterator<(K, V)>>(&self) -> B {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.snapshot::<Vec<_>>())
+            .collect()
+    }
+
+    /// Returns the shard holding the given key.
+    ///
+    /// The bits below the top 7 bits of the hash are used, as
+    /// the map of each shard uses the lowest and the top 7 bits.
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> &TimedMap<K, V, TS, S> {
+        let hash = self.hasher.hash_one(key);
+        let index = (hash << 7).checked_shr(self.shift).unwrap_or(0);
+        &self.shards[index as usize]
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl<K, V> Default for ShardedTimedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, TS, S> Cleanup for ShardedTimedMap<K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone + Send + Sync,
+    V: Clone + Send + Sync,
+    TS: TimeSource + Send + Sync,
+    S: BuildHasher + Send + Sync,
+{
+    fn cleanup(&self) -> usize {
+        self.shards.iter().map(Cleanup::cleanup).sum()
+    }
+
+    fn until_next_expiry(&self) -> Option<Duration> {
+        self.shards
+            .iter()
+            .filter_map(Cleanup::until_next_expiry)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_instant::{Instant, MockClock};
+    use std::{collections::HashMap, sync::Arc, thread};
+
+    #[test]
+    fn get_checked() {
+        let tm: ShardedTimedMap<_, _, Instant> = ShardedTimedMap::new_with_timesource(4);
+        assert!(tm.is_empty());
+
+        assert_eq!(tm.insert("a", 1, Duration::from_millis(10)), None);
+        assert_eq!(tm.insert("a", 2, Duration::from_millis(10)), Some(1));
+        assert_eq!(tm.len(), 1);
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.get(&"a"), Some(2));
+        assert!(tm.contains(&"a"));
+
+        MockClock::advance(Duration::from_millis(1));
+        assert_eq!(tm.get(&"a"), None);
+        assert!(tm.is_empty());
+
+        tm.insert("b", 3, Duration::from_millis(10));
+        assert_eq!(tm.remove(&"b"), Some(3));
+        assert_eq!(tm.remove(&"b"), None);
+    }
+
+    #[test]
+    fn shards() {
+        assert_eq!(ShardedTimedMap::<u64, u64>::with_shards(1).shards(), 1);
+        assert_eq!(ShardedTimedMap::<u64, u64>::with_shards(5).shards(), 8);
+        assert_eq!(
+            ShardedTimedMap::<u64, u64>::new().shards(),
+            DEFAULT_SHARD_COUNT
+        );
+
+        let tm = ShardedTimedMap::with_shards(8);
+        for i in 0..1_000u64 {
+            tm.insert(i, i, Duration::from_secs(60));
+        }
+        assert!(tm.shards.iter().all(|shard| shard.len() > 50));
+
+        let tm = ShardedTimedMap::with_shards(1);
+        tm.insert("a", 1, Duration::from_secs(60));
+        assert_eq!(tm.get(&"a"), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "shard count must not be zero")]
+    fn zero_shards() {
+        let _ = ShardedTimedMap::<u64, u64>::with_shards(0);
+    }
+
+    #[test]
+    fn aggregate() {
+        let tm: ShardedTimedMap<_, _, Instant> = ShardedTimedMap::new_with_timesource(8);
+        for i in 0..100 {
+            tm.insert(
+                i,
+                i,
+                Duration::from_millis(if i % 2 == 0 { 10 } else { 20 }),
+            );
+        }
+        assert_eq!(tm.len(), 100);
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.len(), 50);
+        let snapshot: HashMap<_, _> = tm.snapshot();
+        assert_eq!(snapshot.len(), 50);
+        assert!(snapshot.iter().all(|(k, v)| k == v && k % 2 == 1));
+
+        assert_eq!(tm.until_next_expiry(), Some(Duration::from_millis(9)));
+        assert_eq!(tm.cleanup(), 50);
+        assert_eq!(tm.cleanup(), 0);
+
+        tm.clear();
+        assert!(tm.is_empty());
+        assert_eq!(tm.until_next_expiry(), None);
+    }
+
+    #[test]
+    fn concurrent_inserts() {
+        let tm = Arc::new(ShardedTimedMap::new());
+
+        let handles: Vec<_> = (0..8u64)
+            .map(|t| {
+                let tm = tm.clone();
+                thread::spawn(move || {
+                    for i in 0..1_000 {
+                        let key = t * 1_000 + i;
+                        tm.insert(key, key, Duration::from_secs(60));
+                        assert_eq!(tm.get(&key), Some(key));
+                        if i % 2 == 1 {
+                            assert_eq!(tm.remove(&key), Some(key));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert_eq!(tm.len(), 4_000);
+        assert_eq!(tm.cleanup(), 0);
+    }
+}
@@ -0,0 +1,61 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Statistics about the accesses to a [`TimedMap`](crate::TimedMap)
+/// since it has been created or the statistics have been reset.
+///
+/// This is returned from [`TimedMap::stats`](crate::TimedMap::stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of retrievals which returned a value.
+    pub hits: u64,
+    /// Number of retrievals which did not return a value,
+    /// including retrievals of expired values.
+    pub misses: u64,
+    /// Number of inserted key-value pairs.
+    pub inserts: u64,
+    /// Number of key-value pairs removed because they have
+    /// expired or exceeded the maximum capacity of the map.
+    pub evictions: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Stats {
+    pub(crate) fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn insert(&self) {
+        self.inserts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn evict(&self, n: usize) {
+        self.evictions.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            inserts: self.inserts.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.inserts.store(0, Ordering::Relaxed);
+        self.evictions.store(0, Ordering::Relaxed);
+    }
+}
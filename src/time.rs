@@ -1,12 +1,18 @@
-use std::{
+use core::{
     hash::Hash,
     ops::{Add, AddAssign, Sub, SubAssign},
-    time::{Duration, Instant},
+    time::Duration,
 };
+#[cfg(not(feature = "no-std"))]
+use std::time::{Instant, SystemTime};
 
 /// Defines a time source used to retrieve the
 /// current time and check the expiry of a
 /// [`Value`](crate::Value).
+///
+/// Since version 2.0.0, implementations also have to provide
+/// [`saturating_duration_since`](TimeSource::saturating_duration_since),
+/// which is used to calculate the remaining lifetime of values.
 pub trait TimeSource:
     Add<Duration, Output = Self>
     + AddAssign<Duration>
@@ -19,13 +25,267 @@ pub trait TimeSource:
     + Eq
     + Clone
 {
+    /// Returns the current time.
     fn now() -> Self;
+
+    /// Returns the amount of time elapsed from `earlier`
+    /// to `self`, or zero if `earlier` is later than `self`.
+    fn saturating_duration_since(&self, earlier: &Self) -> Duration;
+
+    /// Returns `self + duration` or [`None`] if the result can
+    /// not be represented.
+    ///
+    /// The default implementation uses the [`Add`] implementation,
+    /// which may panic on overflow.
+    fn checked_add(&self, duration: Duration) -> Option<Self> {
+        Some(self.clone() + duration)
+    }
 }
 
+#[cfg(not(feature = "no-std"))]
 impl TimeSource for Instant {
     fn now() -> Self {
         Instant::now()
     }
+
+    fn saturating_duration_since(&self, earlier: &Self) -> Duration {
+        Instant::saturating_duration_since(self, *earlier)
+    }
+
+    fn checked_add(&self, duration: Duration) -> Option<Self> {
+        Instant::checked_add(self, duration)
+    }
+}
+
+/// Uses the wall-clock time as time source.
+///
+/// In contrast to [`Instant`], expiries are absolute points in
+/// wall-clock time which are meaningful across process restarts.
+/// However, [`SystemTime`] is not monotonic, so adjustments of the
+/// system clock can move the expiry of key-value pairs into the
+/// past or the future.
+///
+/// # Example
+/// ```
+/// use timedmap::TimedMap;
+/// use std::time::{Duration, SystemTime};
+///
+/// let tm = TimedMap::<_, _, SystemTime>::new_with_timesource();
+/// tm.insert("foo", "bar", Duration::from_secs(10));
+/// assert_eq!(tm.get(&"foo"), Some("bar"));
+/// ```
+#[cfg(not(feature = "no-std"))]
+impl TimeSource for SystemTime {
+    fn now() -> Self {
+        SystemTime::now()
+    }
+
+    fn saturating_duration_since(&self, earlier: &Self) -> Duration {
+        self.duration_since(*earlier).unwrap_or_default()
+    }
+
+    fn checked_add(&self, duration: Duration) -> Option<Self> {
+        SystemTime::checked_add(self, duration)
+    }
+}
+
+/// Uses the wall-clock time in milliseconds since the Unix epoch
+/// as time source.
+///
+/// Expiries are plain integers, which can be serialized and compared
+/// across processes and nodes, for example in a distributed cache.
+/// Durations are truncated to millisecond precision.
+///
+/// Like [`SystemTime`], this is not monotonic. Adjustments of the
+/// system clock and clock skew between nodes move the expiry of
+/// key-value pairs into the past or the future, so the clocks of all
+/// nodes sharing deadlines should be synchronized, e.g. via NTP.
+///
+/// # Example
+/// ```
+/// use timedmap::{time::EpochMillis, TimedMap};
+/// use std::time::Duration;
+///
+/// let tm = TimedMap::<_, _, EpochMillis>::new_with_timesource();
+/// tm.insert("foo", "bar", Duration::from_secs(10));
+///
+/// let deadline = tm.get_value(&"foo").unwrap().expires().unwrap().as_millis();
+/// assert!(deadline > 1_600_000_000_000);
+/// ```
+#[cfg(not(feature = "no-std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct EpochMillis(u64);
+
+#[cfg(not(feature = "no-std"))]
+impl EpochMillis {
+    /// Creates the time the given milliseconds after the Unix epoch.
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(millis)
+    }
+
+    /// Returns the milliseconds elapsed since the Unix epoch.
+    pub const fn as_millis(&self) -> u64 {
+        self.0
+    }
+
+    fn checked_add_duration(self, duration: Duration) -> Option<Self> {
+        let millis = u64::try_from(duration.as_millis()).ok()?;
+        self.0.checked_add(millis).map(Self)
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl From<u64> for EpochMillis {
+    fn from(millis: u64) -> Self {
+        Self(millis)
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl From<EpochMillis> for u64 {
+    fn from(t: EpochMillis) -> Self {
+        t.0
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl Add<Duration> for EpochMillis {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self {
+        self.checked_add_duration(rhs)
+            .expect("overflow when adding duration to time")
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl AddAssign<Duration> for EpochMillis {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl Sub<Duration> for EpochMillis {
+    type Output = Self;
+
+    fn sub(self, rhs: Duration) -> Self {
+        u64::try_from(rhs.as_millis())
+            .ok()
+            .and_then(|millis| self.0.checked_sub(millis))
+            .map(Self)
+            .expect("overflow when subtracting duration from time")
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl SubAssign<Duration> for EpochMillis {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl TimeSource for EpochMillis {
+    fn now() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        Self(u64::try_from(since_epoch.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    fn saturating_duration_since(&self, earlier: &Self) -> Duration {
+        Duration::from_millis(self.0.saturating_sub(earlier.0))
+    }
+
+    fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.checked_add_duration(duration)
+    }
+}
+
+/// Time source for the `wasm32-unknown-unknown` target, where
+/// [`Instant::now`] and [`SystemTime::now`] panic.
+///
+/// The current time is retrieved from JavaScript via `Date.now()`,
+/// so it is wall-clock time with millisecond precision. Like
+/// [`SystemTime`], it is not monotonic.
+///
+/// The time source has to be selected explicitly per target, because
+/// the default time source of [`TimedMap`](crate::TimedMap) stays
+/// [`Instant`]. Retrieving the current time panics on targets other
+/// than `wasm32`.
+///
+/// # Example
+/// ```no_run
+/// use timedmap::{time::WebTime, TimedMap};
+/// use std::time::Duration;
+///
+/// let tm = TimedMap::<_, _, WebTime>::new_with_timesource();
+/// tm.insert("foo", "bar", Duration::from_secs(10));
+/// assert_eq!(tm.get(&"foo"), Some("bar"));
+/// ```
+#[cfg(feature = "wasm")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WebTime(Duration);
+
+#[cfg(feature = "wasm")]
+impl WebTime {
+    /// Returns the time elapsed since the Unix epoch.
+    pub fn since_epoch(&self) -> Duration {
+        self.0
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl Add<Duration> for WebTime {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self {
+        Self(self.0 + rhs)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl AddAssign<Duration> for WebTime {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs;
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl Sub<Duration> for WebTime {
+    type Output = Self;
+
+    fn sub(self, rhs: Duration) -> Self {
+        Self(self.0 - rhs)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl SubAssign<Duration> for WebTime {
+    fn sub_assign(&mut self, rhs: Duration) {
+        self.0 -= rhs;
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl TimeSource for WebTime {
+    fn now() -> Self {
+        Self(Duration::from_millis(js_sys::Date::now() as u64))
+    }
+
+    fn saturating_duration_since(&self, earlier: &Self) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(duration).map(Self)
+    }
 }
 
 #[cfg(test)]
@@ -33,4 +293,117 @@ impl TimeSource for mock_instant::Instant {
     fn now() -> Self {
         mock_instant::Instant::now()
     }
+
+    fn saturating_duration_since(&self, earlier: &Self) -> Duration {
+        mock_instant::Instant::saturating_duration_since(self, *earlier)
+    }
+
+    fn checked_add(&self, duration: Duration) -> Option<Self> {
+        // The checked_add implementation of mock_instant truncates
+        // on overflow, so the addition is checked on the durations
+        // since the start of the mock clock instead.
+        let start = mock_instant::Instant::now() - mock_instant::MockClock::time();
+        let since = mock_instant::Instant::duration_since(self, start);
+        since.checked_add(duration).map(|d| start + d)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Cleanup, TimedMap};
+    use std::thread;
+
+    #[test]
+    fn system_time_expiry() {
+        let tm: TimedMap<_, _, SystemTime> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(20));
+        tm.insert("b", 2, Duration::from_millis(200));
+        assert_eq!(tm.get(&"a"), Some(1));
+        assert!(tm.ttl(&"b").unwrap() <= Duration::from_millis(200));
+
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(tm.get(&"b"), Some(2));
+
+        assert!(tm.refresh(&"b", Duration::from_millis(20)));
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn system_time_saturating_duration_since() {
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            TimeSource::saturating_duration_since(&later, &now),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            TimeSource::saturating_duration_since(&now, &later),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn epoch_millis_expiry() {
+        let tm: TimedMap<_, _, EpochMillis> = TimedMap::new_with_timesource();
+        let before = EpochMillis::now();
+        tm.insert("a", 1, Duration::from_millis(20));
+        tm.insert("b", 2, Duration::from_millis(200));
+        assert_eq!(tm.get(&"a"), Some(1));
+        assert!(tm.ttl(&"b").unwrap() <= Duration::from_millis(200));
+
+        let deadline = *tm.get_value(&"b").unwrap().expires().unwrap();
+        assert!(deadline >= before + Duration::from_millis(200));
+        assert!(deadline <= EpochMillis::now() + Duration::from_millis(200));
+
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(tm.get(&"b"), Some(2));
+
+        assert!(tm.refresh(&"b", Duration::from_millis(20)));
+        assert!(*tm.get_value(&"b").unwrap().expires().unwrap() < deadline);
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn epoch_millis_arithmetic() {
+        let t = EpochMillis::from_millis(10_000);
+        let later = t + Duration::from_millis(1_500);
+        assert!(later > t);
+        assert_eq!(later.as_millis(), 11_500);
+        assert_eq!(later - Duration::from_millis(1_500), t);
+        assert_eq!(t + Duration::from_micros(999), t);
+        assert_eq!(
+            later.saturating_duration_since(&t),
+            Duration::from_millis(1_500)
+        );
+        assert_eq!(t.saturating_duration_since(&later), Duration::ZERO);
+        assert_eq!(
+            EpochMillis::from_millis(u64::MAX).checked_add(Duration::from_millis(1)),
+            None
+        );
+        assert_eq!(u64::from(EpochMillis::from(42)), 42);
+        assert!(EpochMillis::now().as_millis() > 1_600_000_000_000);
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn web_time_arithmetic() {
+        let t = WebTime(Duration::from_secs(10));
+        let later = t + Duration::from_secs(1);
+        assert!(later > t);
+        assert_eq!(later.since_epoch(), Duration::from_secs(11));
+        assert_eq!(later - Duration::from_secs(1), t);
+        assert_eq!(later.saturating_duration_since(&t), Duration::from_secs(1));
+        assert_eq!(t.saturating_duration_since(&later), Duration::ZERO);
+        assert_eq!(
+            WebTime(Duration::MAX).checked_add(Duration::from_secs(1)),
+            None
+        );
+    }
 }
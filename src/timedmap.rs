@@ -1,11 +1,44 @@
-use crate::{time::TimeSource, Cleanup, Value};
+use crate::compat::{hash_map, DefaultHashBuilder, DefaultTimeSource, HashMap};
+use crate::{
+    lock::RwLock, stats::Stats, time::TimeSource, CacheStats, Cleanup, Entry, InsertError,
+    MapReadGuard, TimedMapBuilder, Value, ValueRef,
+};
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{
+    borrow::Borrow,
+    fmt,
+    hash::{BuildHasher, Hash},
+    mem,
+    ops::Add,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+#[cfg(not(feature = "no-std"))]
 use std::{
-    collections::HashMap,
-    hash::Hash,
-    sync::RwLock,
-    time::{Duration, Instant},
+    sync::mpsc::{self, Receiver, SyncSender},
+    time::Instant,
 };
 
+type EvictFn<K, V> = Arc<dyn Fn(&K, &V) + Send + Sync>;
+type PolicyFn<K, V> = Arc<dyn Fn(&mut dyn Iterator<Item = (&K, &V)>) -> Option<K> + Send + Sync>;
+
+/// Number of expired key-value pairs buffered by the channel
+/// returned from [`TimedMap::expiration_channel`].
+#[cfg(not(feature = "no-std"))]
+pub const EXPIRATION_CHANNEL_CAPACITY: usize = 1024;
+
+/// Interval in which [`TimedMap::expired`] checks keys whose
+/// values never expire.
+#[cfg(feature = "tokio")]
+pub const EXPIRED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default ratio between the capacity and the length of the map
+/// above which the map is shrunk after a cleanup.
+pub(crate) const DEFAULT_SHRINK_RATIO: f32 = 4.0;
+
+/// Default capacity of the map below which it is never shrunk.
+pub(crate) const DEFAULT_SHRINK_MIN_CAPACITY: usize = 1024;
+
 /// Provides a hash map with expiring key-value pairs.
 ///
 /// # Basic Example
@@ -17,416 +50,4749 @@ use std::{
 /// tm.insert("foo", "bar", Duration::from_secs(10));
 /// assert_eq!(tm.get(&"foo"), Some("bar"));
 /// ```
-#[derive(Debug)]
-pub struct TimedMap<K, V, TS = Instant> {
-    inner: RwLock<HashMap<K, Value<V, TS>>>,
+pub struct TimedMap<K, V, TS = DefaultTimeSource, S = DefaultHashBuilder> {
+    pub(crate) inner: RwLock<HashMap<K, Value<V, TS>, S>>,
+    default_lifetime: Option<Duration>,
+    sliding: bool,
+    inclusive_deadline: bool,
+    strict_lifetimes: bool,
+    track_accesses: bool,
+    lazy_eviction: bool,
+    capacity: Option<usize>,
+    policy: Option<PolicyFn<K, V>>,
+    ticks: AtomicU64,
+    on_evict: Option<EvictFn<K, V>>,
+    #[cfg(not(feature = "no-std"))]
+    expired_tx: RwLock<Option<SyncSender<(K, V)>>>,
+    stats: Option<Stats>,
+    /// Ratio and minimum capacity above which the map is shrunk.
+    shrink: Option<(f32, usize)>,
+}
+
+impl<K, V, TS, S> fmt::Debug for TimedMap<K, V, TS, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    TS: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimedMap")
+            .field("inner", &self.inner)
+            .field("default_lifetime", &self.default_lifetime)
+            .field("sliding", &self.sliding)
+            .field("inclusive_deadline", &self.inclusive_deadline)
+            .field("strict_lifetimes", &self.strict_lifetimes)
+            .field("track_accesses", &self.track_accesses)
+            .field("lazy_eviction", &self.lazy_eviction)
+            .field("capacity", &self.capacity)
+            .field("policy", &self.policy.as_ref().map(|_| ".."))
+            .field("on_evict", &self.on_evict.as_ref().map(|_| ".."))
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+/// Creates an independent copy of the map including the
+/// expiries of all key-value pairs.
+///
+/// Expired key-value pairs which have not been cleaned up yet
+/// are copied as well. The configuration of the map, including
+/// the eviction function, is carried over, but an
+/// [`expiration_channel`](TimedMap::expiration_channel) is not and
+/// the statistics of the copy start from zero.
+impl<K, V, TS, S> Clone for TimedMap<K, V, TS, S>
+where
+    K: Clone,
+    V: Clone,
+    TS: Clone,
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: RwLock::new(self.inner.read().clone()),
+            default_lifetime: self.default_lifetime,
+            sliding: self.sliding,
+            inclusive_deadline: self.inclusive_deadline,
+            strict_lifetimes: self.strict_lifetimes,
+            track_accesses: self.track_accesses,
+            lazy_eviction: self.lazy_eviction,
+            capacity: self.capacity,
+            policy: self.policy.clone(),
+            ticks: AtomicU64::new(self.ticks.load(Ordering::Relaxed)),
+            on_evict: self.on_evict.clone(),
+            #[cfg(not(feature = "no-std"))]
+            expired_tx: RwLock::new(None),
+            stats: self.stats.as_ref().map(|_| Stats::default()),
+            shrink: self.shrink,
+        }
+    }
 }
 
+#[cfg(not(feature = "no-std"))]
 impl<K, V> TimedMap<K, V> {
     /// Create a new instance of [`TimedMap`] with the default
     /// [`TimeSource`] implementation [`Instant`].
     pub fn new() -> Self {
         Self::new_with_timesource()
     }
+
+    /// Create a new instance of [`TimedMap`] with the default
+    /// [`TimeSource`] implementation [`Instant`] where key-value
+    /// pairs added via [`insert_default`](#method.insert_default)
+    /// expire after the given lifetime.
+    pub fn new_with_default_lifetime(lifetime: Duration) -> Self {
+        Self::new().with_default_lifetime(lifetime)
+    }
+
+    /// Create a new instance of [`TimedMap`] with the default
+    /// [`TimeSource`] implementation [`Instant`] and sliding
+    /// expiration with the given default lifetime.
+    ///
+    /// See [`with_sliding_expiration`](#method.with_sliding_expiration)
+    /// for details.
+    pub fn new_sliding(default_lifetime: Duration) -> Self {
+        Self::new_with_default_lifetime(default_lifetime).with_sliding_expiration()
+    }
+
+    /// Create a new instance of [`TimedMap`] with the default
+    /// [`TimeSource`] implementation [`Instant`] which holds
+    /// at most `max` key-value pairs.
+    ///
+    /// See [`with_max_capacity`](#method.with_max_capacity)
+    /// for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn new_with_capacity(max: usize) -> Self {
+        Self::new().with_max_capacity(max)
+    }
+
+    /// Create a new instance of [`TimedMap`] with the default
+    /// [`TimeSource`] implementation [`Instant`] which holds
+    /// at most `max` key-value pairs and evicts the key-value pair
+    /// chosen by `policy` when it is full.
+    ///
+    /// See [`with_eviction_policy`](#method.with_eviction_policy)
+    /// for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn new_with_capacity_and_policy<F>(max: usize, policy: F) -> Self
+    where
+        F: Fn(&mut dyn Iterator<Item = (&K, &V)>) -> Option<K> + Send + Sync + 'static,
+    {
+        Self::new_with_capacity(max).with_eviction_policy(policy)
+    }
+
+    /// Create a new instance of [`TimedMap`] with the default
+    /// [`TimeSource`] implementation [`Instant`] which can hold
+    /// at least `capacity` key-value pairs without reallocating.
+    ///
+    /// In contrast to [`new_with_capacity`](#method.new_with_capacity),
+    /// this does not limit the number of key-value pairs. The map
+    /// is not shrunk below the given capacity after a cleanup.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut tm = Self::new();
+        tm.inner = RwLock::new(HashMap::with_capacity(capacity));
+        if let Some((_, min_capacity)) = &mut tm.shrink {
+            *min_capacity = (*min_capacity).max(capacity);
+        }
+        tm
+    }
 }
 
 impl<K, V, TS> TimedMap<K, V, TS> {
     /// Create a new instance of [`TimedMap`] with a custom
     /// [`TimeSource`] implementation.
     pub fn new_with_timesource() -> Self {
-        Self {
-            inner: RwLock::new(HashMap::new()),
-        }
+        Self::new_with_timesource_and_hasher(DefaultHashBuilder::default())
     }
 }
 
-impl<K, V, TS> TimedMap<K, V, TS>
-where
-    K: Eq + PartialEq + Hash + Clone,
-    V: Clone,
-    TS: TimeSource,
-{
-    /// Add a new key-value pair to the map with the
-    /// given lifetime.
+#[cfg(not(feature = "no-std"))]
+impl<K, V, S> TimedMap<K, V, Instant, S> {
+    /// Create a new instance of [`TimedMap`] with the default
+    /// [`TimeSource`] implementation [`Instant`] which uses
+    /// the given hash builder to hash keys.
     ///
-    /// When the lifetime has passed, the key-value pair
-    /// will be no more accessible.
+    /// Like [`new_with_timesource_and_hasher`](#method.new_with_timesource_and_hasher),
+    /// this can be used to declare a map in a `static`.
+    pub const fn new_with_hasher(hasher: S) -> Self {
+        Self::new_with_timesource_and_hasher(hasher)
+    }
+}
+
+impl<K, V, TS, S> TimedMap<K, V, TS, S> {
+    /// Create a new instance of [`TimedMap`] with a custom
+    /// [`TimeSource`] implementation which uses the given
+    /// hash builder to hash keys.
+    ///
+    /// As this is a `const fn`, it can be used to declare a map in a
+    /// `static`, given a hash builder which can be created in a const
+    /// context. This is not the case for the default hash builder
+    /// `RandomState`, which is seeded randomly at runtime.
     ///
     /// # Example
     /// ```
     /// use timedmap::TimedMap;
-    /// use std::time::Duration;
+    /// use std::hash::{BuildHasherDefault, DefaultHasher};
+    /// use std::time::{Duration, Instant};
     ///
-    /// let tm = TimedMap::new();
-    /// tm.insert("foo", "bar", Duration::from_millis(10));
-    /// assert_eq!(tm.get(&"foo"), Some("bar"));
+    /// static CACHE: TimedMap<&str, i32, Instant, BuildHasherDefault<DefaultHasher>> =
+    ///     TimedMap::new_with_timesource_and_hasher(BuildHasherDefault::new());
     ///
-    /// std::thread::sleep(Duration::from_millis(20));
-    /// assert_eq!(tm.get(&"foo"), None);
+    /// CACHE.insert("foo", 1, Duration::from_secs(10));
+    /// assert_eq!(CACHE.get(&"foo"), Some(1));
     /// ```
-    pub fn insert(&self, key: K, value: V, lifetime: Duration) {
-        let mut m = self.inner.write().unwrap();
-        m.insert(key, Value::new(value, lifetime));
+    pub const fn new_with_timesource_and_hasher(hasher: S) -> Self {
+        Self {
+            inner: RwLock::new(HashMap::with_hasher(hasher)),
+            default_lifetime: None,
+            sliding: false,
+            inclusive_deadline: false,
+            strict_lifetimes: false,
+            track_accesses: false,
+            lazy_eviction: true,
+            capacity: None,
+            policy: None,
+            ticks: AtomicU64::new(0),
+            on_evict: None,
+            #[cfg(not(feature = "no-std"))]
+            expired_tx: RwLock::new(None),
+            stats: None,
+            shrink: Some((DEFAULT_SHRINK_RATIO, DEFAULT_SHRINK_MIN_CAPACITY)),
+        }
     }
 
-    /// Returns a copy of the value corresponding to the
-    /// given key.
+    pub(crate) fn from_builder(builder: TimedMapBuilder<TS, S>) -> Self {
+        Self {
+            default_lifetime: builder.default_lifetime,
+            sliding: builder.sliding,
+            inclusive_deadline: builder.inclusive_deadline,
+            strict_lifetimes: builder.strict_lifetimes,
+            track_accesses: builder.track_accesses,
+            lazy_eviction: builder.lazy_eviction,
+            capacity: builder.capacity,
+            stats: builder.stats.then(Stats::default),
+            shrink: builder.shrink,
+            ..Self::new_with_timesource_and_hasher(builder.hasher)
+        }
+    }
+
+    /// Sets the lifetime used for key-value pairs added via
+    /// [`insert_default`](#method.insert_default).
+    pub fn with_default_lifetime(mut self, lifetime: Duration) -> Self {
+        self.default_lifetime = Some(lifetime);
+        self
+    }
+
+    /// Enables sliding expiration, which resets the lifetime
+    /// of a key-value pair to the default lifetime of the map
+    /// every time it is successfully retrieved via
+    /// [`get`](#method.get), [`get_value`](#method.get_value) or
+    /// an occupied [`entry`](#method.entry), which includes
+    /// [`get_or_insert_with`](#method.get_or_insert_with).
+    /// Values which never expire stay unchanged.
     ///
-    /// [`None`] is returned when the values lifetime has
-    /// been passed.
+    /// Sliding expiration has no effect when no default lifetime
+    /// has been set.
     ///
     /// # Behavior
     ///
-    /// If the key-value pair has expired and not been
-    /// cleaned up before, it will be removed from the
-    /// map on next retrival try.
-    pub fn get(&self, key: &K) -> Option<V> {
-        self.get_value(key).map(|v| v.value())
+    /// Because every read modifies the expiry of the retrieved
+    /// value, reads take the write lock of the map instead of the
+    /// read lock, so concurrent reads block each other.
+    pub fn with_sliding_expiration(mut self) -> Self {
+        self.sliding = true;
+        self
     }
 
-    /// Returns `true` when the map contains a non-expired
-    /// value for the given key.
-    ///
-    /// # Behavior
+    /// Makes the deadline of key-value pairs inclusive, so that
+    /// a key-value pair is already expired exactly at its deadline.
     ///
-    /// Because this method is basically a shorthand for
-    /// [get(key).is_some()](#method.get), it behaves the
-    /// same on retrival of expired pairs.
-    pub fn contains(&self, key: &K) -> bool {
-        self.get(key).is_some()
+    /// By default, a key-value pair is still alive when the current
+    /// time equals its deadline and only expires afterwards. See
+    /// [`Value::is_expired_inclusive_at`] for details.
+    pub fn with_inclusive_deadline(mut self) -> Self {
+        self.inclusive_deadline = true;
+        self
     }
 
-    /// Removes the given key-value pair from the map and
-    /// returns the value if it was previously in the map
-    /// and is not expired.
-    pub fn remove(&self, key: &K) -> Option<V> {
-        let mut m = self.inner.write().unwrap();
-        m.remove(key).and_then(|v| v.value_checked())
+    /// Enables strict lifetimes, which makes inserting a key-value
+    /// pair with a zero lifetime via [`insert`](#method.insert),
+    /// [`insert_many`](#method.insert_many),
+    /// [`insert_if_absent`](#method.insert_if_absent),
+    /// [`insert_keep_ttl`](#method.insert_keep_ttl) or
+    /// [`increment`](#method.increment) panic.
+    ///
+    /// Such key-value pairs expire immediately, which usually
+    /// hints at a misconfigured lifetime. Use
+    /// [`try_insert`](#method.try_insert) to handle this case
+    /// without panicking.
+    pub fn with_strict_lifetimes(mut self) -> Self {
+        self.strict_lifetimes = true;
+        self
     }
 
-    /// Sets the lifetime of the value coresponding to the
-    /// given key to the new lifetime from now.
+    /// Enables tracking the number and time of accesses of every
+    /// key-value pair, which can be retrieved via
+    /// [`access_stats`](#method.access_stats).
     ///
-    /// Returns `true` if a non-expired value exists for the
-    /// given key.
-    pub fn refresh(&self, key: &K, new_lifetime: Duration) -> bool {
-        let Some(mut v) = self.get_value(key) else {
-            return false;
-        };
-
-        let mut m = self.inner.write().unwrap();
-        v.set_expiry(new_lifetime);
-        m.insert(key.clone(), v);
+    /// # Behavior
+    ///
+    /// An access is every retrieval of a non-expired value, for
+    /// example via [`get`](#method.get), and every update of its
+    /// lifetime. Recording an access takes a lock on the accessed
+    /// key-value pair, so reads also write when this is enabled.
+    /// Key-value pairs inserted before enabling this are not
+    /// tracked.
+    pub fn with_access_tracking(mut self) -> Self {
+        self.track_accesses = true;
+        self
+    }
 
-        true
+    /// Disables removing expired key-value pairs on retrieval, so
+    /// that [`get`](#method.get), [`contains`](#method.contains) and
+    /// [`get_value`](#method.get_value) only take the read lock of
+    /// the map.
+    ///
+    /// Expired key-value pairs are still not returned, but they stay
+    /// in the map, and take up memory, until they are removed by a
+    /// [`cleanup`](Cleanup::cleanup). So a cleanup cycle should be
+    /// running when this is disabled.
+    ///
+    /// This has no effect on reads with
+    /// [sliding expiration](#method.with_sliding_expiration), which
+    /// take the write lock anyway.
+    pub fn without_lazy_eviction(mut self) -> Self {
+        self.lazy_eviction = false;
+        self
     }
 
-    /// Extends the lifetime of the value coresponding to the
-    /// given key to the new lifetime from now.
+    /// Limits the number of key-value pairs in the map to `max`.
     ///
-    /// Returns `true` if a non-expired value exists for the
-    /// given key.
-    pub fn extend(&self, key: &K, added_lifetime: Duration) -> bool {
-        let Some(mut v) = self.get_value(key) else {
-            return false;
-        };
+    /// When inserting a new key would exceed the capacity, expired
+    /// key-value pairs are removed first. If the map is still full,
+    /// the least recently used key-value pair is evicted, unless an
+    /// [eviction policy](#method.with_eviction_policy) is set. Accessing
+    /// a key-value pair via [`get`](#method.get) or
+    /// [`entry`](#method.entry) marks it as recently used.
+    ///
+    /// # Behavior
+    ///
+    /// Finding the least recently used key-value pair requires
+    /// iterating over the whole map, so inserts into a full map
+    /// take linear time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn with_max_capacity(mut self, max: usize) -> Self {
+        assert!(max > 0, "capacity must not be zero");
+        self.capacity = Some(max);
+        self
+    }
 
-        let mut m = self.inner.write().unwrap();
-        v.add_expiry(added_lifetime);
-        m.insert(key.clone(), v);
+    /// Sets a function which chooses the key-value pair evicted
+    /// when inserting a new key would exceed the
+    /// [maximum capacity](#method.with_max_capacity) of the map.
+    ///
+    /// Expired key-value pairs are still removed first. If the map
+    /// is full nevertheless, `policy` is called with all key-value
+    /// pairs of the map and returns the key to evict. If it returns
+    /// [`None`] or a key which is not in the map, the least recently
+    /// used key-value pair is evicted instead.
+    ///
+    /// `policy` is called while the write lock of the map is held,
+    /// so it must not access the map itself.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// // Evicts the key-value pair with the smallest value.
+    /// let tm = TimedMap::new()
+    ///     .with_max_capacity(2)
+    ///     .with_eviction_policy(|pairs| pairs.min_by_key(|(_, v)| **v).map(|(k, _)| *k));
+    ///
+    /// tm.insert("foo", 2, Duration::from_secs(10));
+    /// tm.insert("bar", 1, Duration::from_secs(10));
+    /// tm.insert("baz", 3, Duration::from_secs(10));
+    /// assert_eq!(tm.get(&"bar"), None);
+    /// assert_eq!(tm.get(&"foo"), Some(2));
+    /// ```
+    pub fn with_eviction_policy<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(&mut dyn Iterator<Item = (&K, &V)>) -> Option<K> + Send + Sync + 'static,
+    {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
 
-        true
+    /// Sets a function which is called with each expired key-value
+    /// pair removed by [`cleanup`](Cleanup::cleanup) or on retrieval,
+    /// for example via [`get`](#method.get).
+    ///
+    /// The function is called after the write lock of the map has
+    /// been released, so it may access the map itself.
+    ///
+    /// Key-value pairs which are removed explicitly, replaced or
+    /// evicted due to the maximum capacity are not passed to it.
+    pub fn with_on_evict<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.on_evict = Some(Arc::new(f));
+        self
     }
 
-    /// Returns the number of key-value pairs in the map
-    /// which have not been expired.
-    pub fn len(&self) -> usize {
-        let m = self.inner.read().unwrap();
-        m.iter().filter(|(_, v)| !v.is_expired()).count()
+    /// Enables collecting statistics about the accesses to the
+    /// map, which can be retrieved via [`stats`](#method.stats).
+    ///
+    /// Hits and misses are counted by [`get`](#method.get),
+    /// [`contains`](#method.contains) and [`get_value`](#method.get_value).
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(Stats::default());
+        self
     }
 
-    /// Returns `true` when the map does not contain any
-    /// non-expired key-value pair.
-    pub fn is_empty(&self) -> bool {
-        let m = self.inner.read().unwrap();
-        m.len() == 0
+    /// Sets when the map is shrunk after a [`cleanup`](Cleanup::cleanup).
+    ///
+    /// The allocation of the map is shrunk to fit its length, but
+    /// not below `min_capacity`, when its capacity exceeds `ratio`
+    /// times its length as well as `min_capacity`. By default,
+    /// `ratio` is 4 and `min_capacity` is 1024.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not positive.
+    pub fn with_shrink_threshold(mut self, ratio: f32, min_capacity: usize) -> Self {
+        assert!(ratio > 0.0, "shrink ratio must be positive");
+        self.shrink = Some((ratio, min_capacity));
+        self
     }
 
-    /// Clears the map, removing all key-value pairs.
-    pub fn clear(&self) {
-        let mut m = self.inner.write().unwrap();
-        m.clear();
+    /// Disables shrinking the map after a [`cleanup`](Cleanup::cleanup),
+    /// so the allocation of the map never shrinks.
+    pub fn without_shrinking(mut self) -> Self {
+        self.shrink = None;
+        self
     }
 
-    /// Create a snapshot of the current state of the maps
-    /// key-value entries.
+    /// Returns the statistics collected since the map has been
+    /// created or [`reset_stats`](#method.reset_stats) has been
+    /// called.
     ///
-    /// It does only contain all non-expired key-value pairs.
-    pub fn snapshot<B: FromIterator<(K, V)>>(&self) -> B {
-        self.inner
-            .read()
-            .unwrap()
-            .iter()
-            .filter(|(_, v)| !v.is_expired())
-            .map(|(k, v)| (k.clone(), v.value()))
-            .collect()
+    /// All counters are zero if statistics have not been enabled
+    /// via [`with_stats`](#method.with_stats).
+    pub fn stats(&self) -> CacheStats {
+        self.stats.as_ref().map(Stats::snapshot).unwrap_or_default()
     }
 
-    /// Retrieves the raw [`Value`] wrapper by the given key if
-    /// the key-value pair has not been expired yet.
-    ///
-    /// If the given key-value pair is expired and not cleaned
-    /// up yet, it will be removed from the map automatically.
-    pub fn get_value(&self, key: &K) -> Option<Value<V, TS>> {
-        let v = self.get_value_unchecked(key);
-        let Some(v) = v else {
-            return None;
-        };
-        if v.is_expired() {
-            self.remove(key);
-            return None;
+    /// Resets all counters of the collected statistics to zero.
+    pub fn reset_stats(&self) {
+        if let Some(stats) = &self.stats {
+            stats.reset();
         }
-        Some(v)
     }
 
-    /// Retrieves the raw [`Value`] wrapper by the given key
-    /// without checking expiry.
-    pub fn get_value_unchecked(&self, key: &K) -> Option<Value<V, TS>> {
-        let m = self.inner.read().unwrap();
-        m.get(key).cloned()
+    pub(crate) fn record(&self, f: impl FnOnce(&Stats)) {
+        if let Some(stats) = &self.stats {
+            f(stats);
+        }
+    }
+
+    /// Returns the lifetime used for key-value pairs added via
+    /// [`insert_default`](#method.insert_default), if set.
+    pub fn default_lifetime(&self) -> Option<Duration> {
+        self.default_lifetime
     }
 }
 
-impl<K, V, TS> Cleanup for TimedMap<K, V, TS>
+impl<K, V, TS, S> TimedMap<K, V, TS, S>
 where
-    K: Eq + PartialEq + Hash + Clone + Send + Sync,
-    V: Clone + Send + Sync,
-    TS: TimeSource + Send + Sync,
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
 {
-    fn cleanup(&self) {
-        let now = TS::now();
-
-        let mut keys = vec![];
-        {
-            let m = self.inner.read().unwrap();
-            keys.extend(
-                m.iter()
-                    .filter(|(_, val)| val.is_expired_at(&now))
-                    .map(|(key, _)| key)
-                    .cloned(),
-            );
-        }
+    /// Add a new key-value pair to the map with the
+    /// given lifetime.
+    ///
+    /// When the lifetime has passed, the key-value pair
+    /// will be no more accessible.
+    ///
+    /// Returns the previous value for the given key if there
+    /// was one and it has not been expired.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", "bar", Duration::from_millis(10));
+    /// assert_eq!(tm.get(&"foo"), Some("bar"));
+    ///
+    /// std::thread::sleep(Duration::from_millis(20));
+    /// assert_eq!(tm.get(&"foo"), None);
+    /// ```
+    pub fn insert(&self, key: K, value: V, lifetime: Duration) -> Option<V> {
+        self.assert_lifetime(lifetime);
+        self.insert_value(key, Value::new(value, lifetime))
+    }
 
-        if keys.is_empty() {
-            return;
+    /// Add a new key-value pair to the map with the given
+    /// lifetime, unless the lifetime is zero.
+    ///
+    /// In contrast to [`insert`](#method.insert), a zero lifetime
+    /// is rejected with [`InsertError::ZeroLifetime`] regardless of
+    /// whether [strict lifetimes](#method.with_strict_lifetimes)
+    /// are enabled.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::{InsertError, TimedMap};
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// assert_eq!(tm.try_insert("foo", "bar", Duration::from_secs(10)), Ok(()));
+    /// assert_eq!(
+    ///     tm.try_insert("foo", "baz", Duration::ZERO),
+    ///     Err(InsertError::ZeroLifetime)
+    /// );
+    /// assert_eq!(tm.get(&"foo"), Some("bar"));
+    /// ```
+    pub fn try_insert(&self, key: K, value: V, lifetime: Duration) -> Result<(), InsertError> {
+        if lifetime.is_zero() {
+            return Err(InsertError::ZeroLifetime);
         }
+        self.insert_value(key, Value::new(value, lifetime));
+        Ok(())
+    }
 
-        let mut m = self.inner.write().unwrap();
-        for key in keys {
-            m.remove(&key);
+    /// Adds all given key-value pairs to the map which
+    /// expire after the same given lifetime.
+    ///
+    /// In contrast to calling [`insert`](#method.insert) for every
+    /// key-value pair, the write lock of the map is only taken once
+    /// and the deadline is calculated a single time, so all pairs
+    /// expire at the same time.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert_many([("foo", 1), ("bar", 2)], Duration::from_secs(10));
+    /// assert_eq!(tm.len(), 2);
+    /// ```
+    pub fn insert_many<I: IntoIterator<Item = (K, V)>>(&self, entries: I, lifetime: Duration) {
+        self.assert_lifetime(lifetime);
+        let deadline = TS::now().checked_add(lifetime);
+        let mut m = self.inner.write();
+        for (key, value) in entries {
+            let value = match &deadline {
+                Some(deadline) => Value::new_at(value, deadline.clone()),
+                None => Value::new_permanent(value),
+            };
+            self.insert_value_locked(&mut m, key, value);
         }
+    }
 
-        // TODO: Maybe shrink the map down if it exceeds a predefined
-        // capacity, like
-        // if m.capacity() > SOME_CAP_VAL {
-        //     m.shrink_to_fit();
-        // }
+    /// Add a new key-value pair to the map which expires
+    /// at the given deadline.
+    ///
+    /// Returns the previous value for the given key if there
+    /// was one and it has not been expired.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert_at("foo", "bar", Instant::now() + Duration::from_millis(10));
+    /// assert_eq!(tm.get(&"foo"), Some("bar"));
+    ///
+    /// std::thread::sleep(Duration::from_millis(20));
+    /// assert_eq!(tm.get(&"foo"), None);
+    /// ```
+    pub fn insert_at(&self, key: K, value: V, deadline: TS) -> Option<V> {
+        self.insert_value(key, Value::new_at(value, deadline))
     }
-}
 
-impl<K, V> Default for TimedMap<K, V> {
-    fn default() -> Self {
-        Self {
-            inner: Default::default(),
+    /// Add a new key-value pair to the map with the
+    /// default lifetime of the map.
+    ///
+    /// If no default lifetime has been set, the key-value
+    /// pair never expires.
+    ///
+    /// Returns the previous value for the given key if there
+    /// was one and it has not been expired.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new_with_default_lifetime(Duration::from_millis(10));
+    /// tm.insert_default("foo", "bar");
+    /// assert_eq!(tm.get(&"foo"), Some("bar"));
+    ///
+    /// std::thread::sleep(Duration::from_millis(20));
+    /// assert_eq!(tm.get(&"foo"), None);
+    /// ```
+    pub fn insert_default(&self, key: K, value: V) -> Option<V> {
+        match self.default_lifetime {
+            Some(lifetime) => self.insert(key, value, lifetime),
+            None => self.insert_permanent(key, value),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Adds all given key-value pairs to the map with
+    /// the same given lifetime.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.extend_with_lifetime([("foo", 1), ("bar", 2)], Duration::from_secs(10));
+    /// assert_eq!(tm.get(&"bar"), Some(2));
+    /// ```
+    pub fn extend_with_lifetime<I>(&self, iter: I, lifetime: Duration)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (key, value) in iter {
+            self.insert(key, value, lifetime);
+        }
+    }
+
+    /// Copies all non-expired key-value pairs of `other` into
+    /// the map, keeping their expiries. Values of keys which
+    /// are present in both maps are replaced.
+    ///
+    /// # Behavior
+    ///
+    /// Both maps are locked during the merge. The locks are always
+    /// acquired in the same order, so merging two maps into each
+    /// other concurrently does not deadlock.
+    pub fn merge<S2>(&self, other: &TimedMap<K, V, TS, S2>) {
+        let this = (self as *const Self).cast::<()>();
+        let that = (other as *const TimedMap<K, V, TS, S2>).cast::<()>();
+        if this == that {
+            return;
+        }
+
+        let (mut m, o) = if this < that {
+            let m = self.inner.write();
+            (m, other.inner.read())
+        } else {
+            let o = other.inner.read();
+            (self.inner.write(), o)
+        };
+
+        let now = TS::now();
+        for (key, value) in o.iter().filter(|(_, v)| !self.is_expired_at(v, &now)) {
+            self.insert_value_locked(&mut m, key.clone(), value.clone());
+        }
+    }
+
+    /// Adds a new key-value pair to the map with the given
+    /// lifetime if the map does not contain a non-expired value
+    /// for the given key.
+    ///
+    /// Returns `true` if the key-value pair has been inserted.
+    /// An existing non-expired value is left untouched, while an
+    /// expired one is replaced.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// assert!(tm.insert_if_absent("foo", 1, Duration::from_secs(10)));
+    /// assert!(!tm.insert_if_absent("foo", 2, Duration::from_secs(10)));
+    /// assert_eq!(tm.get(&"foo"), Some(1));
+    /// ```
+    pub fn insert_if_absent(&self, key: K, value: V, lifetime: Duration) -> bool {
+        self.assert_lifetime(lifetime);
+        let mut m = self.inner.write();
+        if m.get(&key).is_some_and(|v| !self.is_expired(v)) {
+            return false;
+        }
+        self.insert_value_locked(&mut m, key, Value::new(value, lifetime));
+        true
+    }
+
+    /// Add a new key-value pair to the map with the given
+    /// lifetime, unless the map contains a non-expired value for
+    /// the given key, whose value is replaced while its expiry
+    /// stays unchanged.
+    ///
+    /// In contrast to [`insert`](#method.insert), updating an
+    /// existing value does not reset its lifetime.
+    ///
+    /// Returns the previous value for the given key if there
+    /// was one and it has not been expired.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// assert_eq!(tm.insert_keep_ttl("foo", 1, Duration::from_secs(10)), None);
+    /// assert_eq!(tm.insert_keep_ttl("foo", 2, Duration::from_secs(60)), Some(1));
+    /// assert!(tm.ttl(&"foo").unwrap() <= Duration::from_secs(10));
+    /// ```
+    pub fn insert_keep_ttl(&self, key: K, value: V, lifetime: Duration) -> Option<V> {
+        self.assert_lifetime(lifetime);
+        let mut m = self.inner.write();
+        match m.get_mut(&key) {
+            Some(v) if !self.is_expired(v) => {
+                self.mark_used(v);
+                Some(mem::replace(v.value_mut(), value))
+            }
+            _ => self.insert_value_locked(&mut m, key, Value::new(value, lifetime)),
+        }
+    }
+
+    /// Adds `by` to the non-expired value corresponding to the
+    /// given key and returns the new value.
+    ///
+    /// If there is no such value, `by` is inserted with the given
+    /// lifetime instead. The lifetime of an existing value stays
+    /// unchanged, which allows implementing fixed-window rate
+    /// limits.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let requests = TimedMap::new();
+    /// assert_eq!(requests.increment("client", 1, Duration::from_secs(60)), 1);
+    /// assert_eq!(requests.increment("client", 1, Duration::from_secs(60)), 2);
+    /// ```
+    pub fn increment(&self, key: K, by: V, lifetime: Duration) -> V
+    where
+        V: Add<Output = V> + Default,
+    {
+        self.assert_lifetime(lifetime);
+        let mut m = self.inner.write();
+        if let Some(v) = m.get_mut(&key).filter(|v| !self.is_expired(v)) {
+            let value = v.value_mut();
+            *value = mem::take(value) + by;
+            let value = value.clone();
+            self.mark_used(v);
+            return value;
+        }
+        self.insert_value_locked(&mut m, key, Value::new(by.clone(), lifetime));
+        by
+    }
+
+    /// Calls the given function with the non-expired value
+    /// corresponding to the given key and replaces the value with
+    /// the result of the function.
+    ///
+    /// If the function returns [`None`], the key-value pair is
+    /// removed from the map. Otherwise, the value is replaced in
+    /// place, so its lifetime and tracked accesses stay unchanged.
+    /// The function is passed a copy of the value, so the key-value
+    /// pair is left untouched if the function panics.
+    ///
+    /// Returns the new value or [`None`] if the value has been
+    /// removed or there was no non-expired value, in which case the
+    /// function is not called.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("buf", vec![1], Duration::from_secs(60));
+    ///
+    /// let buf = tm.compute_if_present("buf", |_, mut buf| {
+    ///     buf.push(2);
+    ///     Some(buf)
+    /// });
+    /// assert_eq!(buf, Some(vec![1, 2]));
+    /// ```
+    pub fn compute_if_present<Q, F>(&self, key: &Q, f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&K, V) -> Option<V>,
+    {
+        let mut m = self.inner.write();
+        if self.is_expired(m.get(key)?) {
+            let evicted = m.remove_entry(key);
+            drop(m);
+            self.evicted(evicted);
+            return None;
+        }
+
+        let (k, v) = m.get_key_value(key)?;
+        let Some(value) = f(k, v.value()) else {
+            m.remove(key);
+            return None;
+        };
+        let v = m.get_mut(key).expect("entry exists");
+        *v.value_mut() = value.clone();
+        self.mark_used(v);
+        Some(value)
+    }
+
+    /// Add a new key-value pair to the map which never
+    /// expires.
+    ///
+    /// The key-value pair stays in the map until it is
+    /// removed or its lifetime is set via [`refresh`](#method.refresh).
+    ///
+    /// Returns the previous value for the given key if there
+    /// was one and it has not been expired.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert_permanent("foo", "bar");
+    /// assert_eq!(tm.get(&"foo"), Some("bar"));
+    /// assert_eq!(tm.ttl(&"foo"), None);
+    /// ```
+    pub fn insert_permanent(&self, key: K, value: V) -> Option<V> {
+        self.insert_value(key, Value::new_permanent(value))
+    }
+
+    /// Returns the [`Entry`] for the given key for in-place
+    /// manipulation.
+    ///
+    /// Expired values are treated as vacant entries.
+    ///
+    /// # Behavior
+    ///
+    /// The returned entry holds the write lock of the map
+    /// until it is dropped, so other accesses to the map
+    /// will block in the meantime.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// *tm.entry("foo").or_insert(0, Duration::from_secs(10)).get_mut() += 1;
+    /// *tm.entry("foo").or_insert(0, Duration::from_secs(10)).get_mut() += 1;
+    /// assert_eq!(tm.get(&"foo"), Some(2));
+    /// ```
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, TS, S> {
+        Entry::new(self, key)
+    }
+
+    /// Returns a receiver for the expired key-value pairs removed
+    /// by [`cleanup`](Cleanup::cleanup) or on retrieval, like the
+    /// function set via [`with_on_evict`](#method.with_on_evict).
+    ///
+    /// Only one receiver is supported, so calling this again
+    /// disconnects the previously returned receiver.
+    ///
+    /// # Behavior
+    ///
+    /// The channel buffers up to [`EXPIRATION_CHANNEL_CAPACITY`]
+    /// key-value pairs. When the buffer is full or the receiver has
+    /// been dropped, further key-value pairs are discarded instead
+    /// of blocking the cleanup.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::{Cleanup, TimedMap};
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// let expired = tm.expiration_channel();
+    ///
+    /// tm.insert("foo", "bar", Duration::ZERO);
+    /// std::thread::sleep(Duration::from_millis(1));
+    /// tm.cleanup();
+    ///
+    /// assert_eq!(expired.try_recv(), Ok(("foo", "bar")));
+    /// ```
+    #[cfg(not(feature = "no-std"))]
+    pub fn expiration_channel(&self) -> Receiver<(K, V)> {
+        let (tx, rx) = mpsc::sync_channel(EXPIRATION_CHANNEL_CAPACITY);
+        *self.expired_tx.write() = Some(tx);
+        rx
+    }
+
+    /// Returns a copy of the non-expired value corresponding
+    /// to the given key or inserts the result of `f` with the
+    /// given lifetime if no such value exists.
+    ///
+    /// The lookup and the insertion happen under a single
+    /// lock and `f` is only called when a value is inserted.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// let v = tm.get_or_insert_with("foo", Duration::from_secs(10), || "bar");
+    /// assert_eq!(v, "bar");
+    /// let v = tm.get_or_insert_with("foo", Duration::from_secs(10), || "baz");
+    /// assert_eq!(v, "bar");
+    /// ```
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&self, key: K, lifetime: Duration, f: F) -> V {
+        self.entry(key).or_insert_with(f, lifetime).get().clone()
+    }
+
+    /// Passes a reference to the non-expired value corresponding
+    /// to the given key to `use_fn` and returns its result. If no
+    /// such value exists, the result of `insert_fn` is inserted
+    /// with the given lifetime first.
+    ///
+    /// In contrast to [`get_or_insert_with`](#method.get_or_insert_with),
+    /// the value is never cloned. The lookup, the insertion and
+    /// `use_fn` happen under a single write lock.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// let len = tm.with_or_insert("foo", Duration::from_secs(10), || vec![0u8; 1024], Vec::len);
+    /// assert_eq!(len, 1024);
+    /// ```
+    pub fn with_or_insert<R, G, F>(&self, key: K, lifetime: Duration, insert_fn: G, use_fn: F) -> R
+    where
+        G: FnOnce() -> V,
+        F: FnOnce(&V) -> R,
+    {
+        use_fn(self.entry(key).or_insert_with(insert_fn, lifetime).get())
+    }
+
+    /// Returns a copy of the value corresponding to the
+    /// given key.
+    ///
+    /// [`None`] is returned when the values lifetime has
+    /// been passed.
+    ///
+    /// # Behavior
+    ///
+    /// If the key-value pair has expired and not been
+    /// cleaned up before, it will be removed from the
+    /// map on next retrival try.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_with(key, Value::value)
+    }
+
+    /// Returns copies of the stored key and the value
+    /// corresponding to the given key.
+    ///
+    /// This is useful for keys whose stored form differs from
+    /// the form used for the lookup, while both compare equal.
+    /// Otherwise, this behaves like [`get`](#method.get).
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert(String::from("foo"), 1, Duration::from_secs(60));
+    /// assert_eq!(tm.get_key_value("foo"), Some((String::from("foo"), 1)));
+    /// ```
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let kv = self.get_key_value_recorded(key);
+        self.record(|s| if kv.is_some() { s.hit() } else { s.miss() });
+        kv
+    }
+
+    fn get_key_value_recorded<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(lifetime) = self.sliding_lifetime() {
+            let mut m = self.inner.write();
+            let v = m.get_mut(key)?;
+            if self.is_expired(v) {
+                let evicted = m.remove_entry(key);
+                drop(m);
+                self.evicted(evicted);
+                return None;
+            }
+            if !v.is_permanent() {
+                v.set_expiry(lifetime);
+            }
+            self.mark_used(v);
+            let (k, v) = m.get_key_value(key)?;
+            return Some((k.clone(), v.value()));
+        }
+
+        let m = self.inner.read();
+        let (k, v) = m.get_key_value(key)?;
+        if self.is_expired(v) {
+            drop(m);
+            self.remove_expired(key);
+            return None;
+        }
+        self.mark_used(v);
+        Some((k.clone(), v.value()))
+    }
+
+    /// Returns copies of the values corresponding to the given
+    /// keys in the same order as the keys.
+    ///
+    /// [`None`] is returned for keys which are not present or
+    /// whose values have expired.
+    ///
+    /// # Behavior
+    ///
+    /// The lock of the map is only taken once for all keys. In
+    /// contrast to [`get`](#method.get), expired key-value pairs
+    /// are not removed from the map.
+    pub fn get_many<'a, I>(&self, keys: I) -> Vec<Option<V>>
+    where
+        I: IntoIterator<Item = &'a K>,
+        K: 'a,
+    {
+        let values: Vec<_> = if let Some(lifetime) = self.sliding_lifetime() {
+            let mut m = self.inner.write();
+            keys.into_iter()
+                .map(|key| {
+                    let v = m.get_mut(key).filter(|v| !self.is_expired(v))?;
+                    if !v.is_permanent() {
+                        v.set_expiry(lifetime);
+                    }
+                    self.mark_used(v);
+                    Some(v.value())
+                })
+                .collect()
+        } else {
+            let m = self.inner.read();
+            keys.into_iter()
+                .map(|key| {
+                    let v = m.get(key).filter(|v| !self.is_expired(v))?;
+                    self.mark_used(v);
+                    Some(v.value())
+                })
+                .collect()
+        };
+
+        self.record(|s| {
+            for v in &values {
+                if v.is_some() {
+                    s.hit();
+                } else {
+                    s.miss();
+                }
+            }
+        });
+        values
+    }
+
+    /// Calls the given function with a reference to the
+    /// non-expired value corresponding to the given key and
+    /// returns its result.
+    ///
+    /// In contrast to [`get`](#method.get), the value is not
+    /// cloned. [`None`] is returned when the key is not present
+    /// or the value has expired.
+    ///
+    /// # Behavior
+    ///
+    /// The function is called while holding the read lock of
+    /// the map, so it must not access the map itself. Expired
+    /// key-value pairs are not removed from the map.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", vec![1, 2, 3], Duration::from_secs(10));
+    /// assert_eq!(tm.with_value(&"foo", |v| v.len()), Some(3));
+    /// ```
+    pub fn with_value<Q, R, F>(&self, key: &Q, f: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&V) -> R,
+    {
+        let m = self.inner.read();
+        m.get(key)
+            .filter(|v| !self.is_expired(v))
+            .map(|v| f(v.value_ref()))
+    }
+
+    /// Returns a copy of the value corresponding to the given
+    /// key if it has not been expired yet.
+    ///
+    /// # Behavior
+    ///
+    /// In contrast to [`get`](#method.get), this never modifies
+    /// the map. Only a read lock is taken, expired key-value pairs
+    /// are not removed, sliding lifetimes are not refreshed and
+    /// neither the least recently used order nor the statistics
+    /// are updated.
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner
+            .read()
+            .get(key)
+            .filter(|v| !self.is_expired(v))
+            .map(|v| v.value())
+    }
+
+    /// Returns a reference to the value corresponding to the
+    /// given key, which allows accessing the value without
+    /// cloning it.
+    ///
+    /// The returned [`ValueRef`] holds the maps read lock until
+    /// it is dropped.
+    ///
+    /// # Behavior
+    ///
+    /// In contrast to [`get`](#method.get), expired key-value
+    /// pairs are not removed from the map and sliding lifetimes
+    /// are not refreshed, because only a read lock is taken.
+    pub fn get_ref(&self, key: &K) -> Option<ValueRef<'_, K, V, TS, S>> {
+        let m = self.inner.read();
+        let v = m.get(key).filter(|v| !self.is_expired(v));
+        self.record(|s| if v.is_some() { s.hit() } else { s.miss() });
+        self.mark_used(v?);
+        Some(ValueRef::new(m, key.clone()))
+    }
+
+    /// Locks the map for reading and returns a view of all
+    /// non-expired key-value pairs, which can be iterated without
+    /// cloning them.
+    ///
+    /// The returned [`MapReadGuard`] holds the maps read lock until
+    /// it is dropped, so writes to the map are blocked meanwhile.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", 1, Duration::from_secs(10));
+    /// tm.insert("bar", 2, Duration::from_secs(10));
+    ///
+    /// let m = tm.read();
+    /// assert_eq!(m.iter().map(|(_, v)| v).sum::<i32>(), 3);
+    /// assert_eq!(m.get(&"foo"), Some(&1));
+    /// ```
+    pub fn read(&self) -> MapReadGuard<'_, K, V, TS, S> {
+        MapReadGuard::new(self.inner.read(), TS::now(), self.inclusive_deadline)
+    }
+
+    /// Returns `true` when the map contains a non-expired
+    /// value for the given key.
+    ///
+    /// # Behavior
+    ///
+    /// Because this method is basically a shorthand for
+    /// [get(key).is_some()](#method.get), it behaves the
+    /// same on retrival of expired pairs.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns `true` when the map contains a non-expired
+    /// value for the given key.
+    ///
+    /// # Behavior
+    ///
+    /// In contrast to [`contains`](#method.contains), this only
+    /// takes a read lock on the map. Expired key-value pairs are
+    /// not removed and sliding lifetimes are not refreshed, so
+    /// concurrent readers are never blocked by this check.
+    pub fn contains_live<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner
+            .read()
+            .get(key)
+            .is_some_and(|v| !self.is_expired(v))
+    }
+
+    /// Returns the remaining lifetime of the value
+    /// corresponding to the given key.
+    ///
+    /// [`None`] is returned when the key is not present,
+    /// the value has expired or the value never expires.
+    ///
+    /// # Behavior
+    ///
+    /// In contrast to [`get`](#method.get), expired
+    /// key-value pairs are not removed from the map.
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let m = self.inner.read();
+        m.get(key)
+            .filter(|v| !self.is_expired(v))
+            .and_then(|v| v.remaining())
+    }
+
+    /// Waits until the value corresponding to the given key
+    /// has expired or has been removed.
+    ///
+    /// Resolves immediately if the key is not present or its
+    /// value has already expired.
+    ///
+    /// # Behavior
+    ///
+    /// This sleeps until the current deadline of the value and
+    /// checks the key again afterwards, so refreshed lifetimes are
+    /// respected. A removal of the key before its deadline is only
+    /// noticed once the deadline has passed. Values which never
+    /// expire are checked every [`EXPIRED_POLL_INTERVAL`].
+    ///
+    /// # Example
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", "bar", Duration::from_millis(10));
+    ///
+    /// tm.expired("foo").await;
+    /// assert_eq!(tm.get("foo"), None);
+    /// # });
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn expired<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        loop {
+            let remaining = {
+                let m = self.inner.read();
+                match m.get(key).filter(|v| !self.is_expired(v)) {
+                    Some(v) => v.remaining().unwrap_or(EXPIRED_POLL_INTERVAL),
+                    None => return,
+                }
+            };
+            tokio::time::sleep(remaining).await;
+        }
+    }
+
+    /// Removes the given key-value pair from the map and
+    /// returns the value if it was previously in the map
+    /// and is not expired.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut m = self.inner.write();
+        m.remove(key)
+            .filter(|v| !self.is_expired(v))
+            .map(Value::into_value)
+    }
+
+    /// Removes the non-expired key-value pair corresponding to the
+    /// given key from the map and returns its value, but only if
+    /// `pred` returns `true` for the value.
+    ///
+    /// The value is checked and removed under a single write lock,
+    /// so it can not be replaced in between.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", Err("timeout"), Duration::from_secs(10));
+    /// tm.insert("bar", Ok(1), Duration::from_secs(10));
+    ///
+    /// assert_eq!(tm.remove_if(&"foo", Result::is_err), Some(Err("timeout")));
+    /// assert_eq!(tm.remove_if(&"bar", Result::is_err), None);
+    /// assert_eq!(tm.get(&"bar"), Some(Ok(1)));
+    /// ```
+    pub fn remove_if<Q, F>(&self, key: &Q, pred: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&V) -> bool,
+    {
+        let mut m = self.inner.write();
+        let v = m.get(key)?;
+        if self.is_expired(v) {
+            let evicted = m.remove_entry(key);
+            trace!(len = m.len(); "evicted expired value");
+            drop(m);
+            self.evicted(evicted);
+            return None;
+        }
+        if !pred(v.value_ref()) {
+            return None;
+        }
+        m.remove(key).map(Value::into_value)
+    }
+
+    /// Sets the lifetime of the value coresponding to the
+    /// given key to the new lifetime from now.
+    ///
+    /// Values which never expired before will expire
+    /// after the new lifetime.
+    ///
+    /// Returns `true` if a non-expired value exists for the
+    /// given key.
+    pub fn refresh<Q>(&self, key: &Q, new_lifetime: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.update_live(key, |v| {
+            v.set_expiry(new_lifetime);
+            self.mark_used(v);
+        })
+        .is_some()
+    }
+
+    /// Sets the lifetimes of the values corresponding to all
+    /// given keys to the new lifetime from now.
+    ///
+    /// All values are refreshed under a single write lock and get
+    /// the same deadline. Keys which are not present or whose values
+    /// have expired are skipped, expired values are removed.
+    ///
+    /// Returns the number of refreshed values.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("session", 1, Duration::from_secs(10));
+    /// tm.insert("csrf", 2, Duration::from_secs(10));
+    ///
+    /// let n = tm.refresh_many(&["session", "csrf", "missing"], Duration::from_secs(60));
+    /// assert_eq!(n, 2);
+    /// ```
+    pub fn refresh_many<'a, I>(&self, keys: I, new_lifetime: Duration) -> usize
+    where
+        I: IntoIterator<Item = &'a K>,
+        K: 'a,
+    {
+        let now = TS::now();
+        let deadline = now.checked_add(new_lifetime);
+        let mut m = self.inner.write();
+
+        let mut refreshed = 0;
+        let mut expired = Vec::new();
+        for key in keys {
+            let Some(v) = m.get_mut(key) else {
+                continue;
+            };
+            if self.is_expired_at(v, &now) {
+                expired.push(key);
+                continue;
+            }
+            v.set_deadline(deadline.clone());
+            self.mark_used(v);
+            refreshed += 1;
+        }
+        let evicted: Vec<_> = expired
+            .into_iter()
+            .filter_map(|k| m.remove_entry(k))
+            .collect();
+        drop(m);
+
+        self.evicted(evicted);
+        refreshed
+    }
+
+    /// Resets the lifetime of the value corresponding to the
+    /// given key to the new lifetime from now without retrieving
+    /// the value.
+    ///
+    /// This is an alias for [`refresh`](#method.refresh), which
+    /// updates the expiry in place and never clones the value.
+    ///
+    /// Returns `true` if a non-expired value exists for the
+    /// given key.
+    pub fn touch<Q>(&self, key: &Q, new_lifetime: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.refresh(key, new_lifetime)
+    }
+
+    /// Replaces the value corresponding to the given key with
+    /// `value` while keeping its expiry and returns the previous
+    /// value.
+    ///
+    /// In contrast to [`insert`](#method.insert), this does not
+    /// reset the lifetime and does not insert the key if it is
+    /// not present.
+    ///
+    /// [`None`] is returned when the key is not present or
+    /// the value has expired.
+    pub fn replace<Q>(&self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.update_live(key, |v| {
+            self.mark_used(v);
+            mem::replace(v.value_mut(), value)
+        })
+    }
+
+    /// Returns the value corresponding to the given key and
+    /// sets its lifetime to the new lifetime from now.
+    ///
+    /// [`None`] is returned when the key is not present or
+    /// the value has expired.
+    ///
+    /// # Behavior
+    ///
+    /// The lookup and the update of the lifetime happen under
+    /// a single write lock, so the value can not expire or be
+    /// removed in between.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", "bar", Duration::from_millis(10));
+    /// assert_eq!(tm.get_and_refresh(&"foo", Duration::from_secs(10)), Some("bar"));
+    ///
+    /// std::thread::sleep(Duration::from_millis(20));
+    /// assert_eq!(tm.get(&"foo"), Some("bar"));
+    /// ```
+    pub fn get_and_refresh<Q>(&self, key: &Q, new_lifetime: Duration) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.update_live(key, |v| {
+            v.set_expiry(new_lifetime);
+            self.mark_used(v);
+            v.value()
+        })
+    }
+
+    /// Extends the lifetime of the value coresponding to the
+    /// given key to the new lifetime from now.
+    ///
+    /// Values which never expire stay unchanged.
+    ///
+    /// Returns `true` if a non-expired value exists for the
+    /// given key.
+    pub fn extend<Q>(&self, key: &Q, added_lifetime: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.update_live(key, |v| v.add_expiry(added_lifetime))
+            .is_some()
+    }
+
+    /// Like [`extend`](#method.extend), but also extends the
+    /// lifetime of a value which has expired at most `grace` ago
+    /// and has not been removed from the map yet.
+    ///
+    /// The lifetime is added to the previous expiry, so a value
+    /// which is extended by less than it is overdue stays expired.
+    ///
+    /// Returns `true` if the lifetime of a value has been extended.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", "bar", Duration::ZERO);
+    /// std::thread::sleep(Duration::from_millis(1));
+    ///
+    /// assert!(tm.extend_within_grace(&"foo", Duration::from_secs(60), Duration::from_secs(1)));
+    /// assert_eq!(tm.get(&"foo"), Some("bar"));
+    /// ```
+    pub fn extend_within_grace<Q>(&self, key: &Q, added_lifetime: Duration, grace: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = TS::now();
+        let mut m = self.inner.write();
+        let Some(v) = m.get_mut(key) else {
+            return false;
+        };
+        let overdue = v.expires().map(|e| now.saturating_duration_since(e));
+        if self.is_expired_at(v, &now) && overdue > Some(grace) {
+            let evicted = m.remove_entry(key);
+            drop(m);
+            self.evicted(evicted);
+            return false;
+        }
+        v.add_expiry(added_lifetime);
+        true
+    }
+
+    /// Applies `f` to the value and the deadline of the non-expired
+    /// key-value pair corresponding to the given key in place.
+    ///
+    /// Both are changed under a single write lock, so no other
+    /// access observes the value without its new deadline. Values
+    /// which never expire have no deadline and are not passed to
+    /// `f`.
+    ///
+    /// Returns `true` if `f` has been applied.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", 1, Duration::from_secs(10));
+    ///
+    /// assert!(tm.modify(&"foo", |v, deadline| {
+    ///     *v += 1;
+    ///     *deadline += Duration::from_secs(50);
+    /// }));
+    /// assert_eq!(tm.get(&"foo"), Some(2));
+    /// assert!(tm.ttl(&"foo").unwrap() > Duration::from_secs(50));
+    /// ```
+    pub fn modify<Q, F>(&self, key: &Q, f: F) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&mut V, &mut TS),
+    {
+        self.update_live(key, |v| match v.parts_mut() {
+            (value, Some(deadline)) => {
+                f(value, deadline);
+                true
+            }
+            (_, None) => false,
+        })
+        .unwrap_or(false)
+    }
+
+    /// Returns the number of accesses of the non-expired value
+    /// corresponding to the given key and the time of its last
+    /// access, or of its insertion if it has not been accessed.
+    ///
+    /// [`None`] is returned when the key is not present, the
+    /// value has expired or [access tracking](#method.with_access_tracking)
+    /// is not enabled. This does not count as an access itself.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new().with_access_tracking();
+    /// tm.insert("foo", "bar", Duration::from_secs(10));
+    /// tm.get(&"foo");
+    /// tm.get(&"foo");
+    ///
+    /// let (count, _last_access) = tm.access_stats(&"foo").unwrap();
+    /// assert_eq!(count, 2);
+    /// ```
+    pub fn access_stats<Q>(&self, key: &Q) -> Option<(u64, TS)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let m = self.inner.read();
+        m.get(key)
+            .filter(|v| !self.is_expired(v))
+            .and_then(|v| v.access_stats())
+    }
+
+    /// Returns the number of key-value pairs in the map
+    /// which have not been expired.
+    ///
+    /// This checks the expiry of every key-value pair, so it
+    /// takes `O(n)` time. See [`len_raw`](#method.len_raw) for
+    /// the number of stored key-value pairs.
+    pub fn len(&self) -> usize {
+        let m = self.inner.read();
+        m.iter().filter(|(_, v)| !self.is_expired(v)).count()
+    }
+
+    /// Returns the number of key-value pairs stored in the map,
+    /// including expired ones which have not been cleaned up yet.
+    ///
+    /// In contrast to [`len`](#method.len), this takes `O(1)` time.
+    pub fn len_raw(&self) -> usize {
+        self.inner.read().len()
+    }
+
+    /// Returns the number of expired key-value pairs which are
+    /// still stored in the map, waiting to be cleaned up.
+    ///
+    /// This equals [`len_raw`](#method.len_raw) minus
+    /// [`len`](#method.len), but is counted under a single lock, so
+    /// it can be used to monitor whether the cleanup keeps up.
+    pub fn expired_count(&self) -> usize {
+        let now = TS::now();
+        let m = self.inner.read();
+        m.values().filter(|v| self.is_expired_at(v, &now)).count()
+    }
+
+    /// Returns the number of key-value pairs the map can hold
+    /// without reallocating.
+    ///
+    /// This reports the capacity of the backing map, which also
+    /// holds expired key-value pairs until they are cleaned up.
+    pub fn capacity(&self) -> usize {
+        self.inner.read().capacity()
+    }
+
+    /// Reserves capacity for at least `additional` more key-value
+    /// pairs to be inserted into the map.
+    pub fn reserve(&self, additional: usize) {
+        self.inner.write().reserve(additional);
+    }
+
+    /// Returns `true` when the map does not contain any
+    /// non-expired key-value pair.
+    pub fn is_empty(&self) -> bool {
+        let m = self.inner.read();
+        m.iter().all(|(_, v)| self.is_expired(v))
+    }
+
+    /// Clears the map, removing all key-value pairs.
+    pub fn clear(&self) {
+        let mut m = self.inner.write();
+        m.clear();
+    }
+
+    /// Expires all key-value pairs immediately, including the ones
+    /// which never expire, and sets their deadline to now.
+    ///
+    /// In contrast to [`clear`](#method.clear), the key-value pairs
+    /// stay in the map until they are removed by a
+    /// [`cleanup`](Cleanup::cleanup) or on retrieval, so they are
+    /// passed to the eviction function and the expiration channel.
+    ///
+    /// # Behavior
+    ///
+    /// Invalidated key-value pairs are reported as expired right
+    /// away, even if they are read at the same tick of the time
+    /// source. Key-value pairs which have expired before keep their
+    /// deadline.
+    pub fn invalidate_all(&self) {
+        self.invalidate_matching(|_| true);
+    }
+
+    /// Like [`invalidate_all`](#method.invalidate_all), but only
+    /// expires the non-expired key-value pairs whose key matches
+    /// `pred`.
+    ///
+    /// Returns the number of invalidated key-value pairs.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("tenant:1:foo", 1, Duration::from_secs(10));
+    /// tm.insert("tenant:1:bar", 2, Duration::from_secs(10));
+    /// tm.insert("tenant:2:foo", 3, Duration::from_secs(10));
+    ///
+    /// assert_eq!(tm.invalidate_matching(|k| k.starts_with("tenant:1:")), 2);
+    /// assert_eq!(tm.get(&"tenant:1:foo"), None);
+    /// assert_eq!(tm.get(&"tenant:2:foo"), Some(3));
+    /// ```
+    pub fn invalidate_matching<F: Fn(&K) -> bool>(&self, pred: F) -> usize {
+        let now = TS::now();
+        let mut m = self.inner.write();
+        let mut invalidated = 0;
+        for (k, v) in m.iter_mut() {
+            if !self.is_expired_at(v, &now) && pred(k) {
+                v.invalidate(now.clone());
+                invalidated += 1;
+            }
+        }
+        invalidated
+    }
+
+    /// Removes all key-value pairs from the map and returns
+    /// the non-expired ones.
+    ///
+    /// Collecting the key-value pairs and clearing the map
+    /// happen under a single write lock. Expired key-value
+    /// pairs are discarded.
+    pub fn drain<B: FromIterator<(K, V)>>(&self) -> B {
+        let now = TS::now();
+        let mut m = self.inner.write();
+        m.drain()
+            .filter(|(_, v)| !self.is_expired_at(v, &now))
+            .map(|(k, v)| (k, v.into_value()))
+            .collect()
+    }
+
+    /// Retains only the key-value pairs for which the given
+    /// predicate returns `true`.
+    ///
+    /// Expired key-value pairs are removed regardless of
+    /// the predicate and are not passed to it.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("tenant-a/foo", 1, Duration::from_secs(10));
+    /// tm.insert("tenant-b/foo", 2, Duration::from_secs(10));
+    ///
+    /// tm.retain(|k, _| !k.starts_with("tenant-a/"));
+    /// assert_eq!(tm.get(&"tenant-a/foo"), None);
+    /// assert_eq!(tm.get(&"tenant-b/foo"), Some(2));
+    /// ```
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&self, mut f: F) {
+        let now = TS::now();
+        let mut m = self.inner.write();
+        m.retain(|k, v| !self.is_expired_at(v, &now) && f(k, v.value_ref()));
+    }
+
+    /// Applies the given function to all non-expired values of
+    /// the map in place.
+    ///
+    /// All values are updated under a single write lock. Expired
+    /// key-value pairs are removed and are not passed to `f`.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", 1, Duration::from_secs(10));
+    /// tm.insert("bar", 2, Duration::from_secs(10));
+    ///
+    /// tm.update_all(|_, v| *v -= 1);
+    /// assert_eq!(tm.get(&"foo"), Some(0));
+    /// assert_eq!(tm.get(&"bar"), Some(1));
+    /// ```
+    pub fn update_all<F: FnMut(&K, &mut V)>(&self, f: F) {
+        self.cleanup_and_compact(f);
+    }
+
+    /// Removes all expired key-value pairs from the map like
+    /// [`cleanup`](Cleanup::cleanup) and applies the given function
+    /// to all remaining values in place, in a single pass over the
+    /// map under the write lock.
+    ///
+    /// This allows compacting state held by the values themselves.
+    /// Returns the number of removed key-value pairs.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", vec![1, 2, 3, 4], Duration::from_secs(10));
+    ///
+    /// tm.cleanup_and_compact(|_, events| events.retain(|e| e % 2 == 0));
+    /// assert_eq!(tm.get(&"foo"), Some(vec![2, 4]));
+    /// ```
+    pub fn cleanup_and_compact<F: FnMut(&K, &mut V)>(&self, mut f: F) -> usize {
+        let now = TS::now();
+        let mut m = self.inner.write();
+
+        let expired: Vec<_> = m
+            .iter_mut()
+            .filter_map(|(k, v)| {
+                if self.is_expired_at(v, &now) {
+                    return Some(k.clone());
+                }
+                f(k, v.value_mut());
+                None
+            })
+            .collect();
+        let evicted: Vec<_> = expired.iter().filter_map(|k| m.remove_entry(k)).collect();
+        self.shrink(&mut m);
+        drop(m);
+
+        let removed = evicted.len();
+        self.evicted(evicted);
+        removed
+    }
+
+    /// Removes at most `max` expired key-value pairs from the map
+    /// and returns the number of removed key-value pairs.
+    ///
+    /// In contrast to [`cleanup`](Cleanup::cleanup), scanning the
+    /// map stops as soon as `max` expired key-value pairs have been
+    /// found, so the work of cleaning up a large map can be spread
+    /// over multiple calls.
+    pub fn cleanup_with_limit(&self, max: usize) -> usize {
+        let now = TS::now();
+        let mut m = self.inner.write();
+
+        let expired: Vec<_> = m
+            .iter()
+            .filter(|(_, v)| self.is_expired_at(v, &now))
+            .map(|(k, _)| k.clone())
+            .take(max)
+            .collect();
+        let evicted: Vec<_> = expired.iter().filter_map(|k| m.remove_entry(k)).collect();
+        self.shrink(&mut m);
+        drop(m);
+
+        let removed = evicted.len();
+        self.evicted(evicted);
+        removed
+    }
+
+    /// Removes all expired key-value pairs from the map, passes
+    /// each removed key-value pair to `f` and returns the number
+    /// of removed key-value pairs.
+    ///
+    /// `f` is called after the key-value pairs have been removed
+    /// and the lock on the map has been released, so it may access
+    /// the map. The eviction function and the expiration channel
+    /// are notified as with [`cleanup`](Cleanup::cleanup).
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", 1, Duration::ZERO);
+    /// tm.insert("bar", 2, Duration::from_secs(60));
+    /// # std::thread::sleep(Duration::from_millis(1));
+    ///
+    /// let mut flushed = Vec::new();
+    /// tm.cleanup_with(|k, v| flushed.push((k, v)));
+    /// assert_eq!(flushed, [("foo", 1)]);
+    /// ```
+    pub fn cleanup_with<F: FnMut(K, V)>(&self, mut f: F) -> usize {
+        let now = TS::now();
+        let mut m = self.inner.write();
+
+        let expired: Vec<_> = m
+            .iter()
+            .filter(|(_, v)| self.is_expired_at(v, &now))
+            .map(|(k, _)| k.clone())
+            .collect();
+        let evicted: Vec<_> = expired.iter().filter_map(|k| m.remove_entry(k)).collect();
+        self.shrink(&mut m);
+        drop(m);
+
+        let removed = evicted.len();
+        self.evicted_with(evicted, Some(&mut f));
+        removed
+    }
+
+    /// Removes all expired key-value pairs from the map and
+    /// returns them.
+    ///
+    /// Non-expired key-value pairs stay in the map. This behaves
+    /// like [`cleanup_with`](#method.cleanup_with) collecting the
+    /// removed key-value pairs, so the eviction function and the
+    /// expiration channel are notified as well.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::{collections::HashMap, time::Duration};
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", 1, Duration::ZERO);
+    /// tm.insert("bar", 2, Duration::from_secs(60));
+    /// # std::thread::sleep(Duration::from_millis(1));
+    ///
+    /// let expired: HashMap<_, _> = tm.drain_expired();
+    /// assert_eq!(expired, HashMap::from([("foo", 1)]));
+    /// assert_eq!(tm.get(&"bar"), Some(2));
+    /// ```
+    pub fn drain_expired<B: FromIterator<(K, V)>>(&self) -> B {
+        let mut expired = Vec::new();
+        self.cleanup_with(|k, v| expired.push((k, v)));
+        expired.into_iter().collect()
+    }
+
+    /// Returns the expiry of the non-expired key-value pair
+    /// which expires next or [`None`] if the map does not
+    /// contain any non-expired key-value pairs.
+    ///
+    /// # Behavior
+    ///
+    /// This scans all key-value pairs of the map under a
+    /// read lock, so the cost grows linearly with the size
+    /// of the map.
+    pub fn next_expiry(&self) -> Option<TS> {
+        let now = TS::now();
+        let m = self.inner.read();
+        m.values()
+            .filter(|v| !self.is_expired_at(v, &now))
+            .filter_map(|v| v.expires())
+            .min()
+            .cloned()
+    }
+
+    /// Returns the key of the non-expired key-value pair which
+    /// expires next together with its remaining lifetime, or
+    /// [`None`] if the map does not contain any non-expired
+    /// key-value pairs which expire.
+    ///
+    /// If multiple key-value pairs expire at the same time, any
+    /// of them is returned. Like [`next_expiry`](#method.next_expiry),
+    /// this scans all key-value pairs of the map under a read lock.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", 1, Duration::from_secs(10));
+    /// tm.insert("bar", 2, Duration::from_secs(60));
+    ///
+    /// assert_eq!(tm.first_expiring().map(|(k, _)| k), Some("foo"));
+    /// assert_eq!(tm.last_expiring().map(|(k, _)| k), Some("bar"));
+    /// ```
+    pub fn first_expiring(&self) -> Option<(K, Duration)> {
+        self.expiring(false)
+    }
+
+    /// Returns the key of the non-expired key-value pair which
+    /// expires last together with its remaining lifetime, or
+    /// [`None`] if the map does not contain any non-expired
+    /// key-value pairs which expire.
+    ///
+    /// Key-value pairs which never expire are not considered. See
+    /// [`first_expiring`](#method.first_expiring) for details.
+    pub fn last_expiring(&self) -> Option<(K, Duration)> {
+        self.expiring(true)
+    }
+
+    /// Returns the key and the remaining lifetime of the non-expired
+    /// key-value pair which expires first or, if `last` is set, last.
+    fn expiring(&self, last: bool) -> Option<(K, Duration)> {
+        let now = TS::now();
+        let m = self.inner.read();
+        let expiring = m
+            .iter()
+            .filter(|(_, v)| !self.is_expired_at(v, &now))
+            .filter_map(|(k, v)| Some((k, v.expires()?)));
+        let (k, e) = if last {
+            expiring.max_by_key(|(_, e)| *e)
+        } else {
+            expiring.min_by_key(|(_, e)| *e)
+        }?;
+        Some((k.clone(), e.saturating_duration_since(&now)))
+    }
+
+    /// Counts the non-expired key-value pairs by their remaining
+    /// lifetime.
+    ///
+    /// `buckets` are the ascending upper bounds of the buckets. A
+    /// key-value pair is counted in the first bucket whose bound is
+    /// at least its remaining lifetime. The returned vector has one
+    /// more entry than `buckets`, which counts the key-value pairs
+    /// exceeding the last bound and those which never expire.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::time::Duration;
+    ///
+    /// let tm = TimedMap::new();
+    /// tm.insert("foo", 1, Duration::from_secs(30));
+    /// tm.insert("bar", 2, Duration::from_secs(90));
+    /// tm.insert_permanent("baz", 3);
+    ///
+    /// let buckets = [Duration::from_secs(60), Duration::from_secs(120)];
+    /// assert_eq!(tm.ttl_histogram(&buckets), [1, 1, 1]);
+    /// ```
+    pub fn ttl_histogram(&self, buckets: &[Duration]) -> Vec<usize> {
+        let now = TS::now();
+        let mut counts = vec![0; buckets.len() + 1];
+        let m = self.inner.read();
+        for v in m.values().filter(|v| !self.is_expired_at(v, &now)) {
+            let bucket = match v.remaining_at(&now) {
+                Some(remaining) => buckets.partition_point(|&bound| bound < remaining),
+                None => buckets.len(),
+            };
+            counts[bucket] += 1;
+        }
+        counts
+    }
+
+    /// Create a snapshot of the current state of the maps
+    /// key-value entries.
+    ///
+    /// It does only contain all non-expired key-value pairs.
+    pub fn snapshot<B: FromIterator<(K, V)>>(&self) -> B {
+        self.inner
+            .read()
+            .iter()
+            .filter(|(_, v)| !self.is_expired(v))
+            .map(|(k, v)| (k.clone(), v.value()))
+            .collect()
+    }
+
+    /// Returns copies of all non-expired key-value pairs
+    /// as a [`HashMap`](std::collections::HashMap).
+    #[cfg(not(feature = "no-std"))]
+    pub fn to_hashmap(&self) -> std::collections::HashMap<K, V> {
+        self.snapshot()
+    }
+
+    /// Creates a map from the key-value pairs of the given
+    /// [`HashMap`](std::collections::HashMap), which all expire
+    /// after the given lifetime.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::TimedMap;
+    /// use std::{collections::HashMap, time::Duration};
+    ///
+    /// let m = HashMap::from([("foo", 1), ("bar", 2)]);
+    /// let tm: TimedMap<_, _> = TimedMap::from_hashmap(m, Duration::from_secs(10));
+    /// assert_eq!(tm.get(&"foo"), Some(1));
+    /// ```
+    #[cfg(not(feature = "no-std"))]
+    pub fn from_hashmap<S2>(map: std::collections::HashMap<K, V, S2>, lifetime: Duration) -> Self
+    where
+        S: Default,
+    {
+        let tm = Self::new_with_timesource_and_hasher(S::default());
+        tm.insert_many(map, lifetime);
+        tm
+    }
+
+    /// Create a snapshot of the current state of the maps
+    /// key-value entries including the remaining lifetime of
+    /// each key-value pair.
+    ///
+    /// It does only contain all non-expired key-value pairs. Values
+    /// which never expire are reported with [`Duration::MAX`].
+    pub fn snapshot_with_ttl<B: FromIterator<(K, V, Duration)>>(&self) -> B {
+        let now = TS::now();
+        self.inner
+            .read()
+            .iter()
+            .filter_map(|(k, v)| {
+                let remaining = match v.expires() {
+                    Some(e) => e.saturating_duration_since(&now),
+                    None => Duration::MAX,
+                };
+                (!remaining.is_zero()).then(|| (k.clone(), v.value(), remaining))
+            })
+            .collect()
+    }
+
+    /// Returns an iterator over a snapshot of all non-expired
+    /// key-value pairs.
+    ///
+    /// The key-value pairs are copied out of the map when this
+    /// method is called, so later changes to the map are not
+    /// reflected by the iterator.
+    pub fn iter(&self) -> alloc::vec::IntoIter<(K, V)> {
+        self.snapshot::<Vec<_>>().into_iter()
+    }
+
+    /// Returns a snapshot of the keys of all non-expired
+    /// key-value pairs.
+    pub fn keys(&self) -> Vec<K> {
+        self.inner
+            .read()
+            .iter()
+            .filter(|(_, v)| !self.is_expired(v))
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+
+    /// Returns a snapshot of the values of all non-expired
+    /// key-value pairs.
+    pub fn values(&self) -> Vec<V> {
+        self.inner
+            .read()
+            .values()
+            .filter(|v| !self.is_expired(v))
+            .map(|v| v.value())
+            .collect()
+    }
+
+    /// Retrieves the raw [`Value`] wrapper by the given key if
+    /// the key-value pair has not been expired yet.
+    ///
+    /// If the given key-value pair is expired and not cleaned
+    /// up yet, it will be removed from the map automatically.
+    ///
+    /// The returned [`Value`] is a detached copy, so changing it
+    /// does not affect the map. Use [`modify`](#method.modify) to
+    /// change a value in the map.
+    pub fn get_value<Q>(&self, key: &Q) -> Option<Value<V, TS>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_with(key, Value::clone)
+    }
+
+    /// Passes the non-expired value corresponding to the given key
+    /// to `f` and counts the access as a hit or a miss.
+    fn get_with<Q, R>(&self, key: &Q, f: impl FnOnce(&Value<V, TS>) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let r = self.get_live(key, f);
+        self.record(|s| if r.is_some() { s.hit() } else { s.miss() });
+        r
+    }
+
+    fn get_live<Q, R>(&self, key: &Q, f: impl FnOnce(&Value<V, TS>) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(lifetime) = self.sliding_lifetime() {
+            return self.update_live(key, |v| {
+                if !v.is_permanent() {
+                    v.set_expiry(lifetime);
+                }
+                self.mark_used(v);
+                f(v)
+            });
+        }
+
+        let now = TS::now();
+        {
+            let m = self.inner.read();
+            let v = m.get(key)?;
+            if !self.is_expired_at(v, &now) {
+                self.mark_used(v);
+                return Some(f(v));
+            }
+        }
+        self.remove_expired(key);
+        None
+    }
+
+    /// Retrieves the raw [`Value`] wrapper by the given key
+    /// without checking expiry.
+    pub fn get_value_unchecked<Q>(&self, key: &Q) -> Option<Value<V, TS>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let m = self.inner.read();
+        m.get(key).cloned()
+    }
+
+    pub(crate) fn sliding_lifetime(&self) -> Option<Duration> {
+        self.default_lifetime.filter(|_| self.sliding)
+    }
+
+    /// Applies `f` to the non-expired value corresponding to
+    /// the given key under a single write lock. Expired values
+    /// are removed instead.
+    fn update_live<Q, R>(&self, key: &Q, f: impl FnOnce(&mut Value<V, TS>) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut m = self.inner.write();
+        let v = m.get_mut(key)?;
+        if self.is_expired(v) {
+            let evicted = m.remove_entry(key);
+            trace!(len = m.len(); "evicted expired value");
+            drop(m);
+            self.evicted(evicted);
+            return None;
+        }
+        Some(f(v))
+    }
+
+    /// Removes the value corresponding to the given key only if
+    /// it is still expired, so that a value which has been inserted
+    /// since the expiry was detected is not lost.
+    fn remove_expired<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.lazy_eviction {
+            return;
+        }
+        let now = TS::now();
+        let mut m = self.inner.write();
+        if m.get(key).is_some_and(|v| self.is_expired_at(v, &now)) {
+            let evicted = m.remove_entry(key);
+            trace!(len = m.len(); "evicted expired value");
+            drop(m);
+            self.evicted(evicted);
+        }
+    }
+
+    fn assert_lifetime(&self, lifetime: Duration) {
+        assert!(
+            !(self.strict_lifetimes && lifetime.is_zero()),
+            "zero lifetime inserted into a map with strict lifetimes"
+        );
+    }
+
+    /// Returns `true` if the given value has expired, respecting
+    /// the deadline mode of the map.
+    pub(crate) fn is_expired(&self, v: &Value<V, TS>) -> bool {
+        self.is_expired_at(v, &TS::now())
+    }
+
+    pub(crate) fn is_expired_at(&self, v: &Value<V, TS>, now: &TS) -> bool {
+        v.is_expired_at_with(now, self.inclusive_deadline)
+    }
+
+    /// Counts the given expired key-value pairs as evictions and
+    /// passes them to the eviction function and the expiration
+    /// channel, if set.
+    pub(crate) fn evicted(&self, evicted: impl IntoIterator<Item = (K, Value<V, TS>)>) {
+        self.evicted_with(evicted, None::<fn(K, V)>);
+    }
+
+    /// Like [`evicted`](Self::evicted), but finally passes each
+    /// key-value pair to `f`, if given.
+    ///
+    /// The expiration channel is not locked while the eviction
+    /// function and `f` are called, so they may access the map.
+    fn evicted_with<F: FnMut(K, V)>(
+        &self,
+        evicted: impl IntoIterator<Item = (K, Value<V, TS>)>,
+        mut f: Option<F>,
+    ) {
+        #[cfg(not(feature = "no-std"))]
+        let tx = self.expired_tx.read().clone();
+        for (k, v) in evicted {
+            self.record(|s| s.evict(1));
+            if let Some(on_evict) = &self.on_evict {
+                on_evict(&k, v.value_ref());
+            }
+            let v = v.into_value();
+            #[cfg(not(feature = "no-std"))]
+            let (k, v) = match (&tx, &f) {
+                (Some(tx), Some(_)) => {
+                    let _ = tx.try_send((k.clone(), v.clone()));
+                    (k, v)
+                }
+                (Some(tx), None) => {
+                    let _ = tx.try_send((k, v));
+                    continue;
+                }
+                (None, _) => (k, v),
+            };
+            if let Some(f) = &mut f {
+                f(k, v);
+            }
+        }
+    }
+
+    fn has_eviction_listeners(&self) -> bool {
+        #[cfg(not(feature = "no-std"))]
+        if self.expired_tx.read().is_some() {
+            return true;
+        }
+        self.on_evict.is_some()
+    }
+
+    fn insert_value(&self, key: K, value: Value<V, TS>) -> Option<V> {
+        let mut m = self.inner.write();
+        self.insert_value_locked(&mut m, key, value)
+    }
+
+    pub(crate) fn insert_value_locked(
+        &self,
+        m: &mut HashMap<K, Value<V, TS>, S>,
+        key: K,
+        mut value: Value<V, TS>,
+    ) -> Option<V> {
+        if self.track_accesses {
+            value.track_accesses();
+        }
+        if let Some(capacity) = self.capacity {
+            if !m.contains_key(&key) {
+                let evicted = evict(m, capacity, self.inclusive_deadline, self.policy.as_ref());
+                self.record(|s| s.evict(evicted));
+            }
+        }
+        if self.capacity.is_some() {
+            value.touch(self.tick());
+        }
+        self.record(Stats::insert);
+        let prev = m.insert(key, value);
+        trace!(len = m.len(); "inserted");
+        prev.filter(|v| !self.is_expired(v)).map(Value::into_value)
+    }
+
+    fn tick(&self) -> u64 {
+        self.ticks.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Shrinks the given map according to the shrink threshold.
+    fn shrink(&self, m: &mut HashMap<K, Value<V, TS>, S>) {
+        let Some((ratio, min_capacity)) = self.shrink else {
+            return;
+        };
+        let capacity = m.capacity();
+        if capacity > min_capacity && capacity as f32 > m.len() as f32 * ratio {
+            m.shrink_to(min_capacity);
+        }
+    }
+
+    pub(crate) fn mark_used(&self, v: &Value<V, TS>) {
+        if self.capacity.is_some() {
+            v.touch(self.tick());
+        }
+        if self.track_accesses {
+            v.record_access();
+        }
+    }
+}
+
+/// Removes key-value pairs from the given map until a new
+/// key fits into the given capacity. Expired key-value pairs
+/// are removed first, then the ones chosen by `policy` or the
+/// least recently used ones.
+///
+/// Returns the number of removed key-value pairs.
+fn evict<K, V, TS, S>(
+    m: &mut HashMap<K, Value<V, TS>, S>,
+    capacity: usize,
+    inclusive: bool,
+    policy: Option<&PolicyFn<K, V>>,
+) -> usize
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    if m.len() < capacity {
+        return 0;
+    }
+
+    let len = m.len();
+    let now = TS::now();
+    m.retain(|_, v| !v.is_expired_at_with(&now, inclusive));
+
+    while m.len() >= capacity {
+        let chosen = policy
+            .and_then(|policy| policy(&mut m.iter().map(|(k, v)| (k, v.value_ref()))))
+            .and_then(|key| m.remove(&key));
+        if chosen.is_some() {
+            continue;
+        }
+        let Some(key) = m
+            .iter()
+            .min_by_key(|(_, v)| v.accessed())
+            .map(|(k, _)| k.clone())
+        else {
+            break;
+        };
+        m.remove(&key);
+    }
+
+    len - m.len()
+}
+
+impl<K, V, TS, S> Cleanup for TimedMap<K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone + Send + Sync,
+    V: Clone + Send + Sync,
+    TS: TimeSource + Send + Sync,
+    S: BuildHasher + Send + Sync,
+{
+    fn cleanup(&self) -> usize {
+        let now = TS::now();
+        let mut m = self.inner.write();
+
+        let removed = if self.has_eviction_listeners() {
+            let expired: Vec<_> = m
+                .iter()
+                .filter(|(_, v)| self.is_expired_at(v, &now))
+                .map(|(k, _)| k.clone())
+                .collect();
+            let evicted: Vec<_> = expired.iter().filter_map(|k| m.remove_entry(k)).collect();
+            self.shrink(&mut m);
+            drop(m);
+
+            let removed = evicted.len();
+            self.evicted(evicted);
+            removed
+        } else {
+            let len = m.len();
+            m.retain(|_, v| !self.is_expired_at(v, &now));
+            let removed = len - m.len();
+            self.shrink(&mut m);
+            drop(m);
+            self.record(|s| s.evict(removed));
+            removed
+        };
+
+        debug!(
+            removed,
+            len = self.len_raw(),
+            elapsed:? = TS::now().saturating_duration_since(&now);
+            "cleaned up"
+        );
+        removed
+    }
+
+    fn until_next_expiry(&self) -> Option<Duration> {
+        self.next_expiry()
+            .map(|e| e.saturating_duration_since(&TS::now()))
+    }
+}
+
+impl<K, V, TS, S> FromIterator<(K, V, Duration)> for TimedMap<K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher + Default,
+{
+    /// Creates a map from key-value pairs with their
+    /// individual lifetimes.
+    fn from_iter<I: IntoIterator<Item = (K, V, Duration)>>(iter: I) -> Self {
+        let mut tm = Self::new_with_timesource_and_hasher(S::default());
+        Extend::extend(&mut tm, iter);
+        tm
+    }
+}
+
+impl<K, V, TS, S> Extend<(K, V, Duration)> for TimedMap<K, V, TS, S>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    /// Adds key-value pairs with their individual
+    /// lifetimes to the map.
+    ///
+    /// Because [`TimedMap::extend`] extends the lifetime of
+    /// a single key, this has to be called as
+    /// `Extend::extend(&mut map, iter)`.
+    fn extend<I: IntoIterator<Item = (K, V, Duration)>>(&mut self, iter: I) {
+        for (key, value, lifetime) in iter {
+            self.insert(key, value, lifetime);
+        }
+    }
+}
+
+/// Consumes the map into a [`HashMap`]
+/// of all non-expired key-value pairs.
+#[cfg(not(feature = "no-std"))]
+impl<K, V, TS, S> From<TimedMap<K, V, TS, S>> for std::collections::HashMap<K, V>
+where
+    K: Eq + Hash,
+    V: Clone,
+    TS: TimeSource,
+{
+    fn from(tm: TimedMap<K, V, TS, S>) -> Self {
+        tm.into_iter().collect()
+    }
+}
+
+impl<K, V, TS, S> IntoIterator for TimedMap<K, V, TS, S>
+where
+    V: Clone,
+    TS: TimeSource,
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, TS>;
+
+    /// Consumes the map into an iterator over all
+    /// non-expired key-value pairs.
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.inner.into_inner().into_iter(),
+            now: TS::now(),
+            inclusive_deadline: self.inclusive_deadline,
+        }
+    }
+}
+
+/// An owning iterator over the non-expired key-value pairs
+/// of a [`TimedMap`].
+///
+/// This is created by the `into_iter` method on [`TimedMap`]
+/// provided by the [`IntoIterator`] trait.
+pub struct IntoIter<K, V, TS> {
+    inner: hash_map::IntoIter<K, Value<V, TS>>,
+    now: TS,
+    inclusive_deadline: bool,
+}
+
+impl<K, V, TS> Iterator for IntoIter<K, V, TS>
+where
+    V: Clone,
+    TS: TimeSource,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .by_ref()
+            .find(|(_, v)| !v.is_expired_at_with(&self.now, self.inclusive_deadline))
+            .map(|(k, v)| (k, v.into_value()))
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl<K, V, S: Default> Default for TimedMap<K, V, Instant, S> {
+    fn default() -> Self {
+        Self::new_with_timesource_and_hasher(S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
     use crate::cleanup::Cleanup;
     use mock_instant::{Instant, MockClock};
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn get_checked() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        assert_eq!(tm.len(), 0);
+        assert!(tm.is_empty());
+
+        tm.insert("a", "b", Duration::from_millis(10));
+        assert_eq!(tm.len(), 1);
+        assert!(!tm.is_empty());
+
+        let v = tm.get(&"x");
+        assert_eq!(v, None);
+        assert!(!tm.contains(&"x"));
+
+        MockClock::advance(Duration::from_millis(5));
+        let v = tm.get(&"a");
+        assert_eq!(v, Some("b"));
+        assert!(tm.contains(&"a"));
+        assert_eq!(tm.len(), 1);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(6));
+        let v = tm.get(&"a");
+        assert_eq!(v, None);
+        assert!(!tm.contains(&"a"));
+        assert_eq!(tm.len(), 0);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn remove() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(100));
+        assert_eq!(tm.len(), 2);
+        assert!(!tm.is_empty());
+
+        let v = tm.remove(&"a");
+        assert_eq!(v, Some(1));
+        assert!(!tm.contains(&"a"));
+        assert_eq!(tm.get(&"b"), Some(2));
+        assert!(tm.contains(&"b"));
+        assert_eq!(tm.len(), 1);
+        assert!(!tm.is_empty());
+
+        let v = tm.remove(&"a");
+        assert_eq!(v, None);
+        assert!(!tm.contains(&"a"));
+        assert_eq!(tm.get(&"b"), Some(2));
+        assert!(tm.contains(&"b"));
+        assert_eq!(tm.len(), 1);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(120));
+        let v = tm.remove(&"b");
+        assert_eq!(v, None);
+        assert!(!tm.contains(&"b"));
+        assert_eq!(tm.len(), 0);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn refresh() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(100));
+        assert_eq!(tm.len(), 2);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(60));
+        assert_eq!(tm.get(&"a"), Some(1));
+        assert_eq!(tm.get(&"b"), Some(2));
+        assert_eq!(tm.len(), 2);
+        assert!(!tm.is_empty());
+
+        assert!(tm.refresh(&"b", Duration::from_millis(60)));
+        assert!(!tm.refresh(&"c", Duration::from_millis(60)));
+
+        MockClock::advance(Duration::from_millis(50));
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(tm.get(&"b"), Some(2));
+        assert_eq!(tm.len(), 1);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(50));
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(tm.get(&"b"), None);
+        assert_eq!(tm.len(), 0);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn extend() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(100));
+        assert_eq!(tm.len(), 2);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(60));
+        assert_eq!(tm.get(&"a"), Some(1));
+        assert_eq!(tm.get(&"b"), Some(2));
+        assert_eq!(tm.len(), 2);
+        assert!(!tm.is_empty());
+
+        assert!(tm.extend(&"b", Duration::from_millis(10)));
+        assert!(!tm.extend(&"c", Duration::from_millis(10)));
+
+        MockClock::advance(Duration::from_millis(50));
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(tm.get(&"b"), Some(2));
+        assert_eq!(tm.len(), 1);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(50));
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(tm.get(&"b"), None);
+        assert_eq!(tm.len(), 0);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn cleanup() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+
+        tm.insert("a", 1, Duration::from_millis(5));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(15));
+        assert_eq!(tm.len(), 3);
+        assert!(!tm.is_empty());
+
+        assert_eq!(tm.cleanup(), 0);
+        assert!(tm.contains(&"a"));
+        assert!(tm.contains(&"b"));
+        assert!(tm.contains(&"c"));
+        assert_eq!(tm.len(), 3);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(6));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(!tm.contains(&"a"));
+        assert!(tm.contains(&"b"));
+        assert!(tm.contains(&"c"));
+        assert_eq!(tm.len(), 2);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(5));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(!tm.contains(&"a"));
+        assert!(!tm.contains(&"b"));
+        assert!(tm.contains(&"c"));
+        assert_eq!(tm.len(), 1);
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(5));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(!tm.contains(&"a"));
+        assert!(!tm.contains(&"b"));
+        assert!(!tm.contains(&"c"));
+        assert_eq!(tm.len(), 0);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn clear() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+
+        tm.insert("a", 1, Duration::from_millis(5));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(15));
+        assert_eq!(tm.len(), 3);
+        assert!(!tm.is_empty());
+
+        tm.clear();
+
+        assert!(!tm.contains(&"a"));
+        assert!(!tm.contains(&"b"));
+        assert!(!tm.contains(&"c"));
+        assert_eq!(tm.len(), 0);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn is_empty_with_uncleaned_expired() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+        assert!(!tm.is_empty());
+
+        MockClock::advance(Duration::from_millis(11));
+        assert!(tm.get_value_unchecked(&"a").is_some());
+        assert_eq!(tm.len(), 0);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn entry() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+
+        let incr = |key| {
+            let mut e = tm.entry(key).or_insert(0, Duration::from_millis(10));
+            *e.get_mut() += 1;
+            *e.get()
+        };
+
+        assert_eq!(incr("a"), 1);
+        assert_eq!(incr("a"), 2);
+        assert_eq!(incr("b"), 1);
+
+        MockClock::advance(Duration::from_millis(5));
+        assert_eq!(incr("a"), 3);
+        assert_eq!(tm.get(&"a"), Some(3));
+
+        MockClock::advance(Duration::from_millis(6));
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(incr("a"), 1);
+        assert_eq!(tm.get(&"b"), None);
+
+        match tm.entry("a") {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), &"a");
+                assert_eq!(e.insert(10), 1);
+                assert_eq!(e.remove(), 10);
+            }
+            Entry::Vacant(_) => panic!("entry should be occupied"),
+        }
+        assert_eq!(tm.get(&"a"), None);
+
+        let mut called = false;
+        tm.entry("c")
+            .or_insert_with(|| 5, Duration::from_millis(10));
+        tm.entry("c").or_insert_with(
+            || {
+                called = true;
+                6
+            },
+            Duration::from_millis(10),
+        );
+        assert!(!called);
+        assert_eq!(tm.get(&"c"), Some(5));
+    }
+
+    #[test]
+    fn ttl() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+        assert_eq!(tm.ttl(&"a"), Some(Duration::from_millis(10)));
+        assert_eq!(tm.ttl(&"b"), None);
+
+        MockClock::advance(Duration::from_millis(4));
+        assert_eq!(tm.ttl(&"a"), Some(Duration::from_millis(6)));
+
+        MockClock::advance(Duration::from_millis(6));
+        assert_eq!(tm.ttl(&"a"), Some(Duration::ZERO));
+
+        MockClock::advance(Duration::from_millis(1));
+        assert_eq!(tm.ttl(&"a"), None);
+        assert!(tm.get_value_unchecked(&"a").is_some());
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let tm: TimedMap<String, i32, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a".to_string(), 1, Duration::from_millis(10));
+        tm.insert("b".to_string(), 2, Duration::from_millis(10));
+
+        assert_eq!(tm.get("a"), Some(1));
+        assert!(tm.contains("b"));
+        assert!(!tm.contains("c"));
+        assert!(tm.get_value("a").is_some());
+        assert!(tm.get_value_unchecked("b").is_some());
+
+        assert!(tm.refresh("a", Duration::from_millis(20)));
+        assert!(tm.extend("b", Duration::from_millis(5)));
+        assert!(!tm.refresh("c", Duration::from_millis(20)));
+
+        MockClock::advance(Duration::from_millis(12));
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.get("b"), Some(2));
+
+        assert_eq!(tm.remove("a"), Some(1));
+        assert_eq!(tm.get("a"), None);
+
+        MockClock::advance(Duration::from_millis(5));
+        assert_eq!(tm.get("b"), None);
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        let mut calls = 0;
+
+        let v = tm.get_or_insert_with("a", Duration::from_millis(10), || {
+            calls += 1;
+            1
+        });
+        assert_eq!(v, 1);
+        assert_eq!(calls, 1);
+
+        let v = tm.get_or_insert_with("a", Duration::from_millis(10), || {
+            calls += 1;
+            2
+        });
+        assert_eq!(v, 1);
+        assert_eq!(calls, 1);
+
+        MockClock::advance(Duration::from_millis(11));
+        let v = tm.get_or_insert_with("a", Duration::from_millis(10), || {
+            calls += 1;
+            3
+        });
+        assert_eq!(v, 3);
+        assert_eq!(calls, 2);
+        assert_eq!(tm.get(&"a"), Some(3));
+    }
+
+    #[test]
+    fn insert_returns_previous() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+
+        assert_eq!(tm.insert("a", 1, Duration::from_millis(10)), None);
+        assert_eq!(tm.insert("a", 2, Duration::from_millis(10)), Some(1));
+        assert_eq!(tm.get(&"a"), Some(2));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.insert("a", 3, Duration::from_millis(10)), None);
+        assert_eq!(tm.get(&"a"), Some(3));
+    }
+
+    #[test]
+    fn custom_hasher() {
+        use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault};
+
+        let tm: TimedMap<_, _, Instant, BuildHasherDefault<DefaultHasher>> =
+            TimedMap::new_with_timesource_and_hasher(Default::default());
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(20));
+        assert_eq!(tm.get(&"a"), Some(1));
+        assert_eq!(tm.get(&"b"), Some(2));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(tm.get(&"b"), Some(2));
+
+        let tm: TimedMap<&str, i32, std::time::Instant, BuildHasherDefault<DefaultHasher>> =
+            TimedMap::new_with_hasher(Default::default());
+        tm.insert("a", 1, Duration::from_secs(10));
+        assert_eq!(tm.get(&"a"), Some(1));
+    }
+
+    #[test]
+    fn next_expiry() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        assert_eq!(tm.next_expiry(), None);
+
+        let start = Instant::now();
+        tm.insert("a", 1, Duration::from_millis(20));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(30));
+        assert_eq!(tm.next_expiry(), Some(start + Duration::from_millis(10)));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.next_expiry(), Some(start + Duration::from_millis(20)));
+
+        tm.remove(&"a");
+        assert_eq!(tm.next_expiry(), Some(start + Duration::from_millis(30)));
+
+        MockClock::advance(Duration::from_millis(20));
+        assert_eq!(tm.next_expiry(), None);
+    }
+
+    #[test]
+    fn insert_permanent() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert_permanent("a", 1);
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert_permanent("c", 3);
+        assert_eq!(tm.ttl(&"a"), None);
+        assert_eq!(tm.ttl(&"b"), Some(Duration::from_millis(10)));
+        assert_eq!(
+            tm.next_expiry(),
+            Some(Instant::now() + Duration::from_millis(10))
+        );
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.cleanup(), 1);
+        assert_eq!(tm.len(), 2);
+        assert_eq!(tm.get(&"a"), Some(1));
+        assert_eq!(tm.get(&"b"), None);
+        assert_eq!(tm.next_expiry(), None);
+
+        assert!(tm.extend(&"a", Duration::from_millis(10)));
+        assert_eq!(tm.ttl(&"a"), None);
+
+        assert!(tm.refresh(&"c", Duration::from_millis(10)));
+        assert_eq!(tm.ttl(&"c"), Some(Duration::from_millis(10)));
+
+        MockClock::advance(Duration::from_secs(1_000_000));
+        assert_eq!(tm.cleanup(), 1);
+        assert_eq!(tm.get(&"a"), Some(1));
+        assert_eq!(tm.get(&"c"), None);
+    }
+
+    #[test]
+    fn insert_default() {
+        let tm: TimedMap<_, _, Instant> =
+            TimedMap::new_with_timesource().with_default_lifetime(Duration::from_millis(100));
+        assert_eq!(tm.default_lifetime(), Some(Duration::from_millis(100)));
+
+        tm.insert_default("a", 1);
+        tm.insert("b", 2, Duration::from_millis(200));
+        tm.insert_default("c", 3);
+        tm.insert("c", 3, Duration::from_millis(50));
+
+        MockClock::advance(Duration::from_millis(60));
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.get("c"), None);
+
+        MockClock::advance(Duration::from_millis(50));
+        assert_eq!(tm.get("a"), None);
+        assert_eq!(tm.get("b"), Some(2));
+
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert_default("a", 1);
+        MockClock::advance(Duration::from_secs(1_000_000));
+        assert_eq!(tm.get("a"), Some(1));
+    }
+
+    #[test]
+    fn sliding_expiration() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource()
+            .with_default_lifetime(Duration::from_millis(100))
+            .with_sliding_expiration();
+
+        tm.insert_default("a", 1);
+        tm.insert_permanent("b", 2);
+
+        for _ in 0..5 {
+            MockClock::advance(Duration::from_millis(60));
+            assert_eq!(tm.get("a"), Some(1));
+            assert_eq!(tm.ttl("a"), Some(Duration::from_millis(100)));
+        }
+
+        assert_eq!(tm.get("b"), Some(2));
+        assert_eq!(tm.ttl("b"), None);
+
+        MockClock::advance(Duration::from_millis(101));
+        assert_eq!(tm.get("a"), None);
+        assert!(tm.get_value_unchecked("a").is_none());
+    }
+
+    #[test]
+    fn get_and_refresh() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert_permanent("c", 3);
+
+        MockClock::advance(Duration::from_millis(50));
+        assert_eq!(tm.get_and_refresh("a", Duration::from_millis(200)), Some(1));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(200)));
+
+        assert_eq!(tm.get_and_refresh("b", Duration::from_millis(200)), None);
+        assert!(tm.get_value_unchecked("b").is_none());
+        assert_eq!(tm.get_and_refresh("d", Duration::from_millis(200)), None);
+
+        assert_eq!(tm.get_and_refresh("c", Duration::from_millis(10)), Some(3));
+        assert_eq!(tm.ttl("c"), Some(Duration::from_millis(10)));
+
+        MockClock::advance(Duration::from_millis(199));
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.get("c"), None);
+
+        MockClock::advance(Duration::from_millis(2));
+        assert_eq!(tm.get("a"), None);
+    }
+
+    #[test]
+    fn capacity_evicts_lru() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_max_capacity(3);
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(100));
+        tm.insert("c", 3, Duration::from_millis(100));
+
+        assert_eq!(tm.get("a"), Some(1));
+        tm.insert("d", 4, Duration::from_millis(100));
+        assert_eq!(tm.len(), 3);
+        assert_eq!(tm.get("b"), None);
+
+        assert_eq!(tm.entry("c").key(), &"c");
+        tm.insert("e", 5, Duration::from_millis(100));
+        assert_eq!(tm.get("a"), None);
+        assert_eq!(tm.get("c"), Some(3));
+        assert_eq!(tm.get("d"), Some(4));
+        assert_eq!(tm.get("e"), Some(5));
+
+        tm.insert("c", 6, Duration::from_millis(100));
+        assert_eq!(tm.len(), 3);
+    }
+
+    #[test]
+    fn capacity_prefers_expired() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_max_capacity(3);
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(100));
+
+        MockClock::advance(Duration::from_millis(20));
+        tm.get_or_insert_with("d", Duration::from_millis(100), || 4);
+
+        assert!(tm.get_value_unchecked("b").is_none());
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.get("c"), Some(3));
+        assert_eq!(tm.get("d"), Some(4));
+    }
+
+    #[test]
+    fn retain() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        for i in 0..10 {
+            tm.insert(i, i, Duration::from_millis(100));
+        }
+        tm.insert(10, 10, Duration::from_millis(10));
+
+        MockClock::advance(Duration::from_millis(20));
+        let mut seen = 0;
+        tm.retain(|_, v| {
+            seen += 1;
+            v % 2 == 0
+        });
+
+        assert_eq!(seen, 10);
+        assert!(tm.get_value_unchecked(&10).is_none());
+        assert_eq!(tm.len(), 5);
+        for i in 0..10 {
+            assert_eq!(tm.contains(&i), i % 2 == 0);
+        }
+    }
+
+    #[test]
+    fn iter_keys_values() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert_permanent("c", 3);
+
+        MockClock::advance(Duration::from_millis(20));
+
+        let mut pairs: Vec<_> = tm.iter().collect();
+        pairs.sort();
+        assert_eq!(pairs, vec![("a", 1), ("c", 3)]);
+
+        let mut keys = tm.keys();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "c"]);
+
+        let mut values = tm.values();
+        values.sort();
+        assert_eq!(values, vec![1, 3]);
+    }
+
+    #[test]
+    fn drain() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert_permanent("c", 3);
+
+        MockClock::advance(Duration::from_millis(20));
+
+        let drained: HashMap<_, _> = tm.drain();
+        assert_eq!(drained, HashMap::from([("a", 1), ("c", 3)]));
+        assert!(tm.is_empty());
+        assert!(tm.get_value_unchecked("b").is_none());
+    }
+
+    #[test]
+    fn into_iter() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert_permanent("c", 3);
+
+        MockClock::advance(Duration::from_millis(20));
+
+        let mut pairs = Vec::new();
+        for (k, v) in tm {
+            pairs.push((k, v));
+        }
+        pairs.sort();
+        assert_eq!(pairs, vec![("a", 1), ("c", 3)]);
+    }
+
+    #[test]
+    fn from_iter_and_extend() {
+        let mut tm: TimedMap<_, _, Instant> = vec![
+            ("a", 1, Duration::from_millis(10)),
+            ("b", 2, Duration::from_millis(20)),
+        ]
+        .into_iter()
+        .collect();
+        Extend::extend(&mut tm, [("c", 3, Duration::from_millis(30))]);
+        tm.extend_with_lifetime([("d", 4), ("e", 5)], Duration::from_millis(40));
+
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(10)));
+        assert_eq!(tm.ttl("b"), Some(Duration::from_millis(20)));
+        assert_eq!(tm.ttl("c"), Some(Duration::from_millis(30)));
+        assert_eq!(tm.ttl("d"), Some(Duration::from_millis(40)));
+        assert_eq!(tm.ttl("e"), Some(Duration::from_millis(40)));
+
+        MockClock::advance(Duration::from_millis(15));
+        assert_eq!(tm.get("a"), None);
+        assert_eq!(tm.get("b"), Some(2));
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.get("b"), None);
+        assert_eq!(tm.len(), 3);
+    }
+
+    #[test]
+    fn recovers_from_poisoned_lock() {
+        let tm = std::sync::Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_secs(60));
+
+        let res = {
+            let tm = tm.clone();
+            std::thread::spawn(move || {
+                let _guard = tm.inner.write();
+                panic!("panicking while holding the lock");
+            })
+            .join()
+        };
+        assert!(res.is_err());
+
+        assert_eq!(tm.get("a"), Some(1));
+        tm.insert("b", 2, Duration::from_secs(60));
+        assert_eq!(tm.len(), 2);
+    }
+
+    #[test]
+    fn refresh_and_extend_do_not_resurrect() {
+        let tm = std::sync::Arc::new(TimedMap::new());
+
+        for _ in 0..100 {
+            tm.insert("a", 1, Duration::from_secs(60));
+
+            let worker = {
+                let tm = tm.clone();
+                std::thread::spawn(move || {
+                    for i in 0..200 {
+                        if i % 2 == 1 {
+                            tm.refresh("a", Duration::from_secs(60));
+                        } else {
+                            tm.extend("a", Duration::from_secs(60));
+                        }
+                    }
+                })
+            };
+
+            tm.remove("a");
+            worker.join().unwrap();
+            assert!(tm.get_value_unchecked("a").is_none());
+        }
+    }
+
+    #[test]
+    fn get_does_not_remove_replaced_value() {
+        use std::{
+            cell::RefCell,
+            ops::{Add, AddAssign, Sub, SubAssign},
+            sync::mpsc::{channel, Receiver, Sender},
+        };
+
+        type Pause = (usize, Sender<()>, Receiver<()>);
+
+        thread_local! {
+            static PAUSE: RefCell<Option<Pause>> = const { RefCell::new(None) };
+        }
+
+        /// Wraps the mock clock and blocks a call to `now` on the
+        /// current thread, after skipping the given number of calls,
+        /// until it is resumed.
+        #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        struct Pausing(Instant);
+
+        impl Add<Duration> for Pausing {
+            type Output = Self;
+            fn add(self, rhs: Duration) -> Self {
+                Self(self.0 + rhs)
+            }
+        }
+
+        impl AddAssign<Duration> for Pausing {
+            fn add_assign(&mut self, rhs: Duration) {
+                self.0 += rhs;
+            }
+        }
+
+        impl Sub<Duration> for Pausing {
+            type Output = Self;
+            fn sub(self, rhs: Duration) -> Self {
+                Self(self.0 - rhs)
+            }
+        }
+
+        impl SubAssign<Duration> for Pausing {
+            fn sub_assign(&mut self, rhs: Duration) {
+                self.0 -= rhs;
+            }
+        }
+
+        impl TimeSource for Pausing {
+            fn now() -> Self {
+                let pause = PAUSE.with(|p| {
+                    let mut p = p.borrow_mut();
+                    match p.as_mut()? {
+                        (0, ..) => p.take(),
+                        (skip, ..) => {
+                            *skip -= 1;
+                            None
+                        }
+                    }
+                });
+                if let Some((_, paused, resume)) = pause {
+                    paused.send(()).unwrap();
+                    resume.recv().unwrap();
+                }
+                Self(Instant::now())
+            }
+
+            fn saturating_duration_since(&self, earlier: &Self) -> Duration {
+                self.0.saturating_duration_since(earlier.0)
+            }
+        }
+
+        let tm: std::sync::Arc<TimedMap<_, _, Pausing>> =
+            std::sync::Arc::new(TimedMap::new_with_timesource());
+        tm.insert("a", 1, Duration::from_millis(10));
+
+        let (paused_tx, paused_rx) = channel();
+        let (resume_tx, resume_rx) = channel();
+
+        // The reader sees the expired value, then pauses right before
+        // removing it, skipping the call used to detect the expiry.
+        // The mock clock is thread local, so only the readers clock is
+        // advanced past the expiry.
+        let reader = {
+            let tm = tm.clone();
+            std::thread::spawn(move || {
+                MockClock::advance(Duration::from_millis(20));
+                PAUSE.with(|p| *p.borrow_mut() = Some((1, paused_tx, resume_rx)));
+                tm.get("a")
+            })
+        };
+
+        paused_rx.recv().unwrap();
+        tm.insert("a", 2, Duration::from_secs(60));
+        resume_tx.send(()).unwrap();
+
+        assert_eq!(reader.join().unwrap(), None);
+        assert_eq!(tm.get_value_unchecked("a").map(|v| v.value()), Some(2));
+    }
+
+    #[test]
+    fn sliding_expiration_entry() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource()
+            .with_default_lifetime(Duration::from_millis(50))
+            .with_sliding_expiration();
+
+        let mut calls = 0;
+        for _ in 0..5 {
+            tm.get_or_insert_with("a", Duration::from_millis(50), || {
+                calls += 1;
+                1
+            });
+            MockClock::advance(Duration::from_millis(30));
+        }
+        assert_eq!(calls, 1);
+
+        MockClock::advance(Duration::from_millis(30));
+        assert_eq!(tm.get("a"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity must not be zero")]
+    fn zero_capacity() {
+        let _: TimedMap<&str, i32> = TimedMap::new_with_capacity(0);
+    }
+
+    #[test]
+    fn overflowing_lifetime() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_secs(u64::MAX));
+        tm.insert("b", 2, Duration::from_secs(10));
+        for _ in 0..3 {
+            assert!(tm.extend("b", Duration::from_secs(u64::MAX / 2)));
+        }
+
+        MockClock::advance(Duration::from_secs(1_000_000));
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.get("b"), Some(2));
+        assert_eq!(tm.ttl("b"), None);
+    }
+
+    /// Large value which counts how often it has been cloned.
+    struct Large {
+        id: u32,
+        data: Vec<u8>,
+        clones: Arc<AtomicUsize>,
+    }
+
+    impl Large {
+        fn new(id: u32, clones: &Arc<AtomicUsize>) -> Self {
+            Self {
+                id,
+                data: vec![0; 1 << 20],
+                clones: clones.clone(),
+            }
+        }
+    }
+
+    impl Clone for Large {
+        fn clone(&self) -> Self {
+            self.clones.fetch_add(1, Ordering::Relaxed);
+            Self {
+                id: self.id,
+                data: self.data.clone(),
+                clones: self.clones.clone(),
+            }
+        }
+    }
+
+    #[test]
+    fn with_value() {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", Large::new(42, &clones), Duration::from_millis(10));
+
+        assert_eq!(tm.with_value("a", |v| v.id), Some(42));
+        assert_eq!(tm.with_value("a", |v| v.data.len()), Some(1 << 20));
+        assert_eq!(tm.with_value("b", |v| v.id), None);
+        assert_eq!(clones.load(Ordering::Relaxed), 0);
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.with_value("a", |v| v.id), None);
+    }
 
     #[test]
-    fn get_checked() {
+    fn insert_if_absent() {
         let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
-        assert_eq!(tm.len(), 0);
+
+        assert!(tm.insert_if_absent("a", 1, Duration::from_millis(10)));
+        assert_eq!(tm.get("a"), Some(1));
+
+        MockClock::advance(Duration::from_millis(5));
+        assert!(!tm.insert_if_absent("a", 2, Duration::from_millis(100)));
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(5)));
+
+        MockClock::advance(Duration::from_millis(6));
+        assert!(tm.insert_if_absent("a", 3, Duration::from_millis(100)));
+        assert_eq!(tm.get("a"), Some(3));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn insert_at() {
+        let deadline = Instant::now() + Duration::from_millis(100);
+        MockClock::advance(Duration::from_millis(30));
+
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert_at("a", 1, deadline);
+        assert_eq!(tm.get_value("a").unwrap().expires(), Some(&deadline));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(70)));
+
+        MockClock::advance(Duration::from_millis(70));
+        assert_eq!(tm.get("a"), Some(1));
+
+        MockClock::advance(Duration::from_millis(1));
+        assert_eq!(tm.get("a"), None);
+    }
+
+    #[test]
+    fn on_evict() {
+        let evicted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_on_evict({
+            let evicted = evicted.clone();
+            move |k: &&str, v: &i32| evicted.lock().unwrap().push((*k, *v))
+        });
+
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(100));
+        tm.insert("d", 4, Duration::from_millis(10));
+        tm.remove("d");
+
+        MockClock::advance(Duration::from_millis(20));
+        assert_eq!(tm.cleanup(), 2);
+
+        let mut got = evicted.lock().unwrap().clone();
+        got.sort();
+        assert_eq!(got, vec![("a", 1), ("b", 2)]);
+
+        MockClock::advance(Duration::from_millis(100));
+        assert_eq!(tm.get("c"), None);
+        assert_eq!(evicted.lock().unwrap().last(), Some(&("c", 3)));
+        assert_eq!(evicted.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn expiration_channel() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        let rx = tm.expiration_channel();
+
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(100));
+
+        MockClock::advance(Duration::from_millis(20));
+        assert_eq!(tm.cleanup(), 2);
+
+        let mut got: Vec<_> = rx.try_iter().collect();
+        got.sort();
+        assert_eq!(got, vec![("a", 1), ("b", 2)]);
+
+        drop(rx);
+        for i in 0..EXPIRATION_CHANNEL_CAPACITY + 1 {
+            tm.insert("d", i as i32, Duration::ZERO);
+            MockClock::advance(Duration::from_millis(1));
+            assert_eq!(tm.get("d"), None);
+        }
+
+        let rx = tm.expiration_channel();
+        for i in 0..EXPIRATION_CHANNEL_CAPACITY + 1 {
+            tm.insert(if i % 2 == 1 { "e" } else { "f" }, i as i32, Duration::ZERO);
+            MockClock::advance(Duration::from_millis(1));
+            tm.cleanup();
+        }
+        assert_eq!(rx.try_iter().count(), EXPIRATION_CHANNEL_CAPACITY);
+    }
+
+    #[test]
+    fn stats() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource()
+            .with_max_capacity(2)
+            .with_stats();
+
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(100));
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.get("c"), None);
+
+        MockClock::advance(Duration::from_millis(20));
+        assert_eq!(tm.get("a"), None);
+        assert!(tm.contains("b"));
+
+        tm.insert("c", 3, Duration::from_millis(100));
+        tm.insert("d", 4, Duration::from_millis(100));
+
+        assert_eq!(
+            tm.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 2,
+                inserts: 4,
+                evictions: 2,
+            }
+        );
+
+        tm.reset_stats();
+        assert_eq!(tm.stats(), CacheStats::default());
+
+        MockClock::advance(Duration::from_millis(200));
+        assert_eq!(tm.cleanup(), 2);
+        assert_eq!(tm.stats().evictions, 2);
+
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.get("a");
+        assert_eq!(tm.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn increment() {
+        let tm: TimedMap<_, u32, Instant> = TimedMap::new_with_timesource();
+
+        for i in 1..=5 {
+            assert_eq!(tm.increment("a", 1, Duration::from_millis(100)), i);
+            MockClock::advance(Duration::from_millis(10));
+        }
+        assert_eq!(tm.increment("a", 10, Duration::from_millis(100)), 15);
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(50)));
+
+        MockClock::advance(Duration::from_millis(51));
+        assert_eq!(tm.increment("a", 1, Duration::from_millis(100)), 1);
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(100)));
+        assert_eq!(tm.increment("b", 3, Duration::from_millis(100)), 3);
+    }
+
+    #[test]
+    fn get_ref() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", vec![1, 2, 3], Duration::from_millis(10));
+
+        {
+            let v = tm.get_ref(&"a").unwrap();
+            assert_eq!(v.key(), &"a");
+            assert_eq!(v.value(), &vec![1, 2, 3]);
+            assert_eq!(v.len(), 3);
+        }
+        assert!(tm.get_ref(&"b").is_none());
+
+        std::thread::scope(|s| {
+            let v = tm.get_ref(&"a").unwrap();
+            let writer = s.spawn(|| tm.insert("b", vec![], Duration::from_millis(10)));
+
+            std::thread::sleep(Duration::from_millis(20));
+            assert!(!writer.is_finished());
+            assert_eq!(*v, vec![1, 2, 3]);
+
+            drop(v);
+            writer.join().unwrap();
+        });
+        assert!(tm.contains("b"));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert!(tm.get_ref(&"a").is_none());
+        assert!(tm.get_value_unchecked("a").is_some());
+    }
+
+    #[test]
+    fn contains_live() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+
+        assert!(tm.contains_live("a"));
+        assert!(!tm.contains_live("b"));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert!(!tm.contains_live("a"));
+        assert!(tm.get_value_unchecked("a").is_some());
+
+        assert!(!tm.contains("a"));
+        assert!(tm.get_value_unchecked("a").is_none());
+    }
+
+    #[test]
+    fn peek() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_stats();
+        tm.insert("a", 1, Duration::from_millis(10));
+
+        assert_eq!(tm.peek("a"), Some(1));
+        assert_eq!(tm.peek("b"), None);
+
+        MockClock::advance(Duration::from_millis(11));
+        for _ in 0..3 {
+            assert_eq!(tm.peek("a"), None);
+        }
+        assert!(tm.get_value_unchecked("a").is_some());
+        assert_eq!(
+            tm.stats(),
+            CacheStats {
+                inserts: 1,
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(tm.cleanup(), 1);
+        assert!(tm.get_value_unchecked("a").is_none());
+    }
+
+    #[test]
+    fn shrink_after_cleanup() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        for i in 0..10_000 {
+            tm.insert(i, i, Duration::from_millis(10));
+        }
+        tm.insert(-1, -1, Duration::from_millis(100));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.cleanup(), 10_000);
+        assert!(tm.capacity() < 2 * DEFAULT_SHRINK_MIN_CAPACITY);
+        assert_eq!(tm.get(&-1), Some(-1));
+
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().without_shrinking();
+        for i in 0..10_000 {
+            tm.insert(i, i, Duration::from_millis(10));
+        }
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.cleanup(), 10_000);
+        assert!(tm.capacity() >= 10_000);
+
+        let tm: TimedMap<_, _, Instant> =
+            TimedMap::new_with_timesource().with_shrink_threshold(2.0, 100_000);
+        for i in 0..10_000 {
+            tm.insert(i, i, Duration::from_millis(10));
+        }
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.cleanup(), 10_000);
+        assert!(tm.capacity() >= 10_000);
+    }
+
+    #[test]
+    fn with_capacity() {
+        let tm: TimedMap<i32, i32> = TimedMap::with_capacity(2_000);
+        assert!(tm.capacity() >= 2_000);
         assert!(tm.is_empty());
 
-        tm.insert("a", "b", Duration::from_millis(10));
+        tm.cleanup();
+        assert!(tm.capacity() >= 2_000);
+
+        tm.reserve(5_000);
+        assert!(tm.capacity() >= 5_000);
+    }
+
+    #[test]
+    fn cleanup_with_limit() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        for i in 0..250 {
+            tm.insert(i, i, Duration::from_millis(10));
+        }
+        tm.insert(-1, -1, Duration::from_millis(100));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.cleanup_with_limit(0), 0);
+
+        let mut calls = 0;
+        loop {
+            let removed = tm.cleanup_with_limit(100);
+            assert!(removed <= 100);
+            if removed == 0 {
+                break;
+            }
+            calls += 1;
+        }
+        assert_eq!(calls, 3);
+        assert_eq!(tm.inner.read().len(), 1);
+        assert_eq!(tm.get(&-1), Some(-1));
+    }
+
+    #[test]
+    fn get_many() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_stats();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(20));
+        tm.insert("c", 3, Duration::from_millis(20));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(
+            tm.get_many(&["c", "a", "x", "b"]),
+            vec![Some(3), None, None, Some(2)]
+        );
+        assert_eq!(tm.get_many([]), vec![]);
+
+        let stats = tm.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 2));
+
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource()
+            .with_default_lifetime(Duration::from_millis(10))
+            .with_sliding_expiration();
+        tm.insert_default("a", 1);
+
+        MockClock::advance(Duration::from_millis(5));
+        assert_eq!(tm.get_many(&["a"]), vec![Some(1)]);
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn refresh_and_extend_do_not_clone() {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", Large::new(1, &clones), Duration::from_millis(10));
+
+        assert!(tm.refresh("a", Duration::from_millis(20)));
+        assert!(tm.extend("a", Duration::from_millis(10)));
+        assert!(!tm.refresh("b", Duration::from_millis(20)));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(30)));
+        assert_eq!(clones.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn clone() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(20));
+        tm.insert_permanent("c", 3);
+
+        let cloned = tm.clone();
+        for k in ["a", "b", "c"] {
+            assert_eq!(cloned.get(k), tm.get(k));
+            assert_eq!(cloned.ttl(k), tm.ttl(k));
+        }
+
+        tm.insert("a", 10, Duration::from_millis(100));
+        tm.remove("b");
+        cloned.insert("d", 4, Duration::from_millis(10));
+        assert_eq!(cloned.get("a"), Some(1));
+        assert_eq!(cloned.get("b"), Some(2));
+        assert_eq!(tm.get("d"), None);
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(cloned.get("a"), None);
+        assert_eq!(tm.get("a"), Some(10));
+    }
+
+    #[test]
+    fn merge() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(10));
+
+        let other: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        other.insert("b", 20, Duration::from_millis(30));
+        other.insert("c", 30, Duration::from_millis(20));
+        other.insert("d", 40, Duration::from_millis(5));
+
+        MockClock::advance(Duration::from_millis(6));
+        tm.merge(&other);
+        tm.merge(&tm);
+
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.get("b"), Some(20));
+        assert_eq!(tm.ttl("b"), Some(Duration::from_millis(24)));
+        assert_eq!(tm.get("c"), Some(30));
+        assert_eq!(tm.get("d"), None);
+        assert_eq!(other.len(), 2);
+    }
+
+    #[test]
+    fn merge_both_directions() {
+        let a = Arc::new(TimedMap::<i32, i32, Instant>::new_with_timesource());
+        let b = Arc::new(TimedMap::<i32, i32, Instant>::new_with_timesource());
+        for i in 0..100 {
+            a.insert(i, i, Duration::from_secs(10));
+            b.insert(-i, i, Duration::from_secs(10));
+        }
+
+        std::thread::scope(|s| {
+            s.spawn(|| (0..100).for_each(|_| a.merge(&b)));
+            s.spawn(|| (0..100).for_each(|_| b.merge(&a)));
+        });
+        assert_eq!(a.len(), 199);
+    }
+
+    #[test]
+    fn snapshot_with_ttl() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(30));
+        tm.insert_permanent("c", 3);
+
+        MockClock::advance(Duration::from_millis(10));
+        let mut snapshot: Vec<_> = tm.snapshot_with_ttl();
+        snapshot.sort();
+        assert_eq!(
+            snapshot,
+            vec![("b", 2, Duration::from_millis(20)), ("c", 3, Duration::MAX),]
+        );
+    }
+
+    #[test]
+    fn compute_if_present() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", vec![1], Duration::from_millis(10));
+        tm.insert("b", vec![2], Duration::from_millis(10));
+        tm.insert("c", vec![3], Duration::from_millis(1));
+
+        MockClock::advance(Duration::from_millis(2));
+
+        let r = tm.compute_if_present("a", |k, mut v| {
+            assert_eq!(k, &"a");
+            v.push(10);
+            Some(v)
+        });
+        assert_eq!(r, Some(vec![1, 10]));
+        assert_eq!(tm.get("a"), Some(vec![1, 10]));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(8)));
+
+        assert_eq!(tm.compute_if_present("b", |_, _| None), None);
+        assert_eq!(tm.get("b"), None);
+
+        let called = std::cell::Cell::new(false);
+        let call = |_: &&str, v| {
+            called.set(true);
+            Some(v)
+        };
+        assert_eq!(tm.compute_if_present("c", call), None);
+        assert_eq!(tm.compute_if_present("x", call), None);
+        assert!(!called.get());
+        assert!(tm.get_value_unchecked("c").is_none());
+    }
+
+    #[test]
+    fn touch() {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", Large::new(7, &clones), Duration::from_millis(10));
+
+        MockClock::advance(Duration::from_millis(8));
+        assert!(tm.touch("a", Duration::from_millis(10)));
+        assert!(!tm.touch("b", Duration::from_millis(10)));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(10)));
+
+        MockClock::advance(Duration::from_millis(8));
+        assert_eq!(tm.with_value("a", |v| v.id), Some(7));
+        assert_eq!(clones.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn deadline_boundary() {
+        let exclusive: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        let inclusive: TimedMap<_, _, Instant> =
+            TimedMap::new_with_timesource().with_inclusive_deadline();
+        for tm in [&exclusive, &inclusive] {
+            tm.insert("a", 1, Duration::from_millis(100));
+        }
+
+        MockClock::advance(Duration::from_millis(100));
+        assert_eq!(exclusive.get("a"), Some(1));
+        assert_eq!(exclusive.len(), 1);
+        assert_eq!(exclusive.cleanup(), 0);
+
+        assert_eq!(inclusive.len(), 0);
+        assert!(!inclusive.contains_live("a"));
+        assert_eq!(inclusive.peek("a"), None);
+        assert_eq!(inclusive.with_value("a", |v| *v), None);
+        assert_eq!(inclusive.cleanup(), 1);
+
+        MockClock::advance(Duration::from_millis(1));
+        assert_eq!(exclusive.get("a"), None);
+    }
+
+    /// Key which compares equal regardless of the case.
+    #[derive(Debug, Clone)]
+    struct CaseInsensitive(&'static str);
+
+    impl PartialEq for CaseInsensitive {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.eq_ignore_ascii_case(other.0)
+        }
+    }
+
+    impl Eq for CaseInsensitive {}
+
+    impl std::hash::Hash for CaseInsensitive {
+        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+            self.0.to_ascii_lowercase().hash(state);
+        }
+    }
+
+    #[test]
+    fn get_key_value() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert(CaseInsensitive("Foo"), 1, Duration::from_millis(100));
+
+        let (k, v) = tm.get_key_value(&CaseInsensitive("foo")).unwrap();
+        assert_eq!(k.0, "Foo");
+        assert_eq!(v, 1);
+
+        MockClock::advance(Duration::from_millis(101));
+        assert_eq!(tm.get_key_value(&CaseInsensitive("FOO")), None);
+        assert!(tm.get_value_unchecked(&CaseInsensitive("foo")).is_none());
+    }
+
+    #[test]
+    fn get_key_value_sliding() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource()
+            .with_default_lifetime(Duration::from_millis(100))
+            .with_sliding_expiration();
+        tm.insert_default(CaseInsensitive("Foo"), 1);
+
+        MockClock::advance(Duration::from_millis(60));
+        let (k, _) = tm.get_key_value(&CaseInsensitive("FOO")).unwrap();
+        assert_eq!(k.0, "Foo");
+        assert_eq!(
+            tm.ttl(&CaseInsensitive("foo")),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn replace() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        let deadline = *tm.get_value("a").unwrap().expires().unwrap();
+
+        MockClock::advance(Duration::from_millis(60));
+        assert_eq!(tm.replace("a", 2), Some(1));
+        assert_eq!(tm.get("a"), Some(2));
+        assert_eq!(tm.get_value("a").unwrap().expires(), Some(&deadline));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(40)));
+
+        assert_eq!(tm.replace("b", 1), None);
+        assert!(tm.get_value_unchecked("b").is_none());
+
+        MockClock::advance(Duration::from_millis(41));
+        assert_eq!(tm.replace("a", 3), None);
+        assert!(tm.get_value_unchecked("a").is_none());
+    }
+
+    #[test]
+    fn len_raw() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(200));
+        assert_eq!(tm.len(), 2);
+        assert_eq!(tm.len_raw(), 2);
+
+        MockClock::advance(Duration::from_millis(101));
         assert_eq!(tm.len(), 1);
-        assert!(!tm.is_empty());
+        assert_eq!(tm.len_raw(), 2);
 
-        let v = tm.get(&"x");
-        assert_eq!(v, None);
-        assert!(!tm.contains(&"x"));
+        tm.cleanup();
+        assert_eq!(tm.len(), 1);
+        assert_eq!(tm.len_raw(), 1);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn expired() {
+        let tm = TimedMap::new();
+        tm.insert("a", 1, Duration::from_millis(50));
+        tm.insert("b", 2, Duration::from_millis(10));
+
+        let start = std::time::Instant::now();
+        tm.expired("a").await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(150), "{elapsed:?}");
+        assert_eq!(tm.get("a"), None);
+
+        let start = std::time::Instant::now();
+        tm.expired("b").await;
+        tm.expired("c").await;
+        assert!(start.elapsed() < Duration::from_millis(10));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn expired_after_refresh() {
+        let tm = Arc::new(TimedMap::new());
+        tm.insert("a", 1, Duration::from_millis(30));
+
+        let start = std::time::Instant::now();
+        let refresher = {
+            let tm = tm.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                tm.refresh("a", Duration::from_millis(50));
+            })
+        };
+        tm.expired("a").await;
+        refresher.await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(70));
+    }
+
+    #[test]
+    fn insert_many() {
+        let tm: TimedMap<String, i32, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a".into(), 0, Duration::from_millis(500));
+        tm.insert_many(
+            (0..100).map(|i| (i.to_string(), i)),
+            Duration::from_millis(100),
+        );
+        tm.insert_many([("a".into(), 1)], Duration::from_millis(100));
+        assert_eq!(tm.len(), 101);
+        assert_eq!(tm.get("42"), Some(42));
+        assert_eq!(tm.get("a"), Some(1));
+
+        let deadline = tm.get_value("a").unwrap().expires().cloned();
+        for (_, v) in tm.inner.read().iter() {
+            assert_eq!(v.expires(), deadline.as_ref());
+        }
+
+        MockClock::advance(Duration::from_millis(101));
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn zero_lifetime_lenient() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        assert_eq!(tm.insert("a", 1, Duration::ZERO), None);
+        MockClock::advance(Duration::from_nanos(1));
+        assert_eq!(tm.get("a"), None);
+
+        assert_eq!(
+            tm.try_insert("a", 1, Duration::ZERO),
+            Err(InsertError::ZeroLifetime)
+        );
+        assert!(tm.get_value_unchecked("a").is_none());
+        assert_eq!(tm.try_insert("a", 1, Duration::from_millis(1)), Ok(()));
+        assert_eq!(tm.get("a"), Some(1));
+    }
+
+    #[test]
+    fn zero_lifetime_strict() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_strict_lifetimes();
+        assert_eq!(
+            tm.try_insert("a", 1, Duration::ZERO),
+            Err(InsertError::ZeroLifetime)
+        );
+        assert_eq!(tm.insert("a", 1, Duration::from_millis(1)), None);
+        assert_eq!(tm.get("a"), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "zero lifetime")]
+    fn zero_lifetime_strict_insert() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_strict_lifetimes();
+        tm.insert("a", 1, Duration::ZERO);
+    }
+
+    #[test]
+    #[should_panic(expected = "zero lifetime")]
+    fn zero_lifetime_strict_increment() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_strict_lifetimes();
+        tm.increment("a", 1, Duration::ZERO);
+    }
+
+    #[test]
+    fn access_stats() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_access_tracking();
+        tm.insert("a", 1, Duration::from_millis(100));
+        let inserted = Instant::now();
+        assert_eq!(tm.access_stats("a"), Some((0, inserted)));
+
+        for _ in 0..3 {
+            MockClock::advance(Duration::from_millis(10));
+            assert_eq!(tm.get("a"), Some(1));
+        }
+        assert_eq!(tm.get("b"), None);
+        assert_eq!(tm.access_stats("a"), Some((3, Instant::now())));
+        assert_eq!(tm.access_stats("b"), None);
+
+        tm.insert("a", 2, Duration::from_millis(100));
+        assert_eq!(tm.access_stats("a"), Some((0, Instant::now())));
+
+        MockClock::advance(Duration::from_millis(101));
+        assert_eq!(tm.access_stats("a"), None);
+    }
+
+    #[test]
+    fn access_stats_disabled() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(100));
+        assert_eq!(tm.get("a"), Some(1));
+        assert_eq!(tm.access_stats("a"), None);
+    }
+
+    #[test]
+    fn hashmap_round_trip() {
+        let m = std::collections::HashMap::from([("a", 1), ("b", 2)]);
+        let tm: TimedMap<_, _, Instant> =
+            TimedMap::from_hashmap(m.clone(), Duration::from_millis(100));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(100)));
+        assert_eq!(tm.to_hashmap(), m);
+
+        tm.insert("c", 3, Duration::from_millis(50));
+        MockClock::advance(Duration::from_millis(51));
+        assert_eq!(tm.to_hashmap(), m);
+        assert_eq!(std::collections::HashMap::from(tm), m);
+    }
+
+    #[test]
+    fn cleanup_with() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_stats();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(100));
+        let rx = tm.expiration_channel();
 
-        MockClock::advance(Duration::from_millis(5));
-        let v = tm.get(&"a");
-        assert_eq!(v, Some("b"));
-        assert!(tm.contains(&"a"));
+        MockClock::advance(Duration::from_millis(11));
+        let mut evicted = Vec::new();
+        assert_eq!(
+            tm.cleanup_with(|k, v| {
+                assert!(!tm.contains_live(&k));
+                evicted.push((k, v));
+            }),
+            2
+        );
+
+        evicted.sort();
+        assert_eq!(evicted, [("a", 1), ("b", 2)]);
         assert_eq!(tm.len(), 1);
-        assert!(!tm.is_empty());
+        assert_eq!(tm.stats().evictions, 2);
+        assert_eq!(rx.try_iter().count(), 2);
 
-        MockClock::advance(Duration::from_millis(6));
-        let v = tm.get(&"a");
-        assert_eq!(v, None);
-        assert!(!tm.contains(&"a"));
-        assert_eq!(tm.len(), 0);
-        assert!(tm.is_empty());
+        assert_eq!(tm.cleanup_with(|_, _| panic!("nothing expired")), 0);
     }
 
     #[test]
-    fn remove() {
+    fn extend_within_grace() {
         let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
-        tm.insert("a", 1, Duration::from_millis(100));
-        tm.insert("b", 2, Duration::from_millis(100));
-        assert_eq!(tm.len(), 2);
-        assert!(!tm.is_empty());
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(100));
 
-        let v = tm.remove(&"a");
-        assert_eq!(v, Some(1));
-        assert!(!tm.contains(&"a"));
+        MockClock::advance(Duration::from_millis(15));
+        assert!(!tm.extend(&"a", Duration::from_millis(100)));
+        assert!(tm.extend_within_grace(&"b", Duration::from_millis(100), Duration::from_millis(5)));
+        assert_eq!(tm.ttl("b"), Some(Duration::from_millis(95)));
         assert_eq!(tm.get(&"b"), Some(2));
-        assert!(tm.contains(&"b"));
-        assert_eq!(tm.len(), 1);
-        assert!(!tm.is_empty());
 
-        let v = tm.remove(&"a");
-        assert_eq!(v, None);
-        assert!(!tm.contains(&"a"));
-        assert_eq!(tm.get(&"b"), Some(2));
-        assert!(tm.contains(&"b"));
-        assert_eq!(tm.len(), 1);
-        assert!(!tm.is_empty());
+        assert!(tm.extend_within_grace(&"c", Duration::from_millis(10), Duration::ZERO));
+        assert_eq!(tm.ttl("c"), Some(Duration::from_millis(95)));
+        assert!(!tm.extend_within_grace(&"d", Duration::from_millis(10), Duration::from_secs(1)));
+    }
 
-        MockClock::advance(Duration::from_millis(120));
-        let v = tm.remove(&"b");
-        assert_eq!(v, None);
-        assert!(!tm.contains(&"b"));
-        assert_eq!(tm.len(), 0);
-        assert!(tm.is_empty());
+    #[test]
+    fn extend_within_grace_long_expired() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+
+        MockClock::advance(Duration::from_millis(20));
+        assert!(!tm.extend_within_grace(
+            &"a",
+            Duration::from_millis(100),
+            Duration::from_millis(5)
+        ));
+        assert!(tm.get_value_unchecked(&"a").is_none());
     }
 
     #[test]
-    fn refresh() {
+    fn update_all() {
         let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
         tm.insert("a", 1, Duration::from_millis(100));
         tm.insert("b", 2, Duration::from_millis(100));
-        assert_eq!(tm.len(), 2);
-        assert!(!tm.is_empty());
+        tm.insert("c", 3, Duration::from_millis(10));
+        tm.insert_permanent("d", 4);
 
-        MockClock::advance(Duration::from_millis(60));
-        assert_eq!(tm.get(&"a"), Some(1));
-        assert_eq!(tm.get(&"b"), Some(2));
-        assert_eq!(tm.len(), 2);
-        assert!(!tm.is_empty());
+        MockClock::advance(Duration::from_millis(11));
+        let mut visited = Vec::new();
+        tm.update_all(|k, v| {
+            visited.push(*k);
+            *v += 10;
+        });
 
-        assert!(tm.refresh(&"b", Duration::from_millis(60)));
-        assert!(!tm.refresh(&"c", Duration::from_millis(60)));
+        visited.sort();
+        assert_eq!(visited, ["a", "b", "d"]);
+        assert_eq!(tm.get(&"a"), Some(11));
+        assert_eq!(tm.get(&"b"), Some(12));
+        assert_eq!(tm.get(&"d"), Some(14));
+        assert!(tm.get_value_unchecked(&"c").is_none());
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(89)));
+    }
 
-        MockClock::advance(Duration::from_millis(50));
+    #[test]
+    fn with_or_insert() {
+        #[derive(Debug)]
+        struct Large {
+            data: Vec<u8>,
+            clones: Arc<AtomicUsize>,
+        }
+
+        impl Clone for Large {
+            fn clone(&self) -> Self {
+                self.clones.fetch_add(1, Ordering::Relaxed);
+                Self {
+                    data: self.data.clone(),
+                    clones: self.clones.clone(),
+                }
+            }
+        }
+
+        let clones = Arc::new(AtomicUsize::new(0));
+        let constructed = AtomicUsize::new(0);
+        let new = || {
+            constructed.fetch_add(1, Ordering::Relaxed);
+            Large {
+                data: vec![1; 1 << 16],
+                clones: clones.clone(),
+            }
+        };
+
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        let len = |v: &Large| v.data.len();
+        assert_eq!(
+            tm.with_or_insert("a", Duration::from_millis(10), new, len),
+            1 << 16
+        );
+        assert_eq!(
+            tm.with_or_insert("a", Duration::from_millis(10), new, len),
+            1 << 16
+        );
+        assert_eq!(constructed.load(Ordering::Relaxed), 1);
+        assert_eq!(clones.load(Ordering::Relaxed), 0);
+
+        MockClock::advance(Duration::from_millis(11));
+        tm.with_or_insert("a", Duration::from_millis(10), new, len);
+        assert_eq!(constructed.load(Ordering::Relaxed), 2);
+        assert_eq!(clones.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn without_lazy_eviction() {
+        let evicted = Arc::new(AtomicUsize::new(0));
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource()
+            .without_lazy_eviction()
+            .with_on_evict({
+                let evicted = evicted.clone();
+                move |_, _| {
+                    evicted.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(20));
+
+        MockClock::advance(Duration::from_millis(11));
         assert_eq!(tm.get(&"a"), None);
+        assert!(!tm.contains(&"a"));
+        assert!(tm.get_value(&"a").is_none());
+        assert_eq!(tm.get_key_value(&"a"), None);
+        assert!(tm.get_value_unchecked(&"a").is_some());
+        assert_eq!(tm.len_raw(), 2);
+        assert_eq!(evicted.load(Ordering::Relaxed), 0);
         assert_eq!(tm.get(&"b"), Some(2));
-        assert_eq!(tm.len(), 1);
-        assert!(!tm.is_empty());
 
-        MockClock::advance(Duration::from_millis(50));
+        assert_eq!(tm.cleanup(), 1);
+        assert_eq!(evicted.load(Ordering::Relaxed), 1);
+        assert_eq!(tm.len_raw(), 1);
+
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+        MockClock::advance(Duration::from_millis(11));
         assert_eq!(tm.get(&"a"), None);
-        assert_eq!(tm.get(&"b"), None);
-        assert_eq!(tm.len(), 0);
-        assert!(tm.is_empty());
+        assert!(tm.get_value_unchecked(&"a").is_none());
     }
 
     #[test]
-    fn extend() {
+    fn refresh_many() {
         let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
-        tm.insert("a", 1, Duration::from_millis(100));
-        tm.insert("b", 2, Duration::from_millis(100));
-        assert_eq!(tm.len(), 2);
-        assert!(!tm.is_empty());
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(20));
+        tm.insert("c", 3, Duration::from_millis(5));
+        tm.insert_permanent("d", 4);
+        tm.insert("e", 5, Duration::from_millis(20));
 
-        MockClock::advance(Duration::from_millis(60));
-        assert_eq!(tm.get(&"a"), Some(1));
-        assert_eq!(tm.get(&"b"), Some(2));
-        assert_eq!(tm.len(), 2);
-        assert!(!tm.is_empty());
+        MockClock::advance(Duration::from_millis(6));
+        let keys = ["a", "b", "c", "d", "missing"];
+        assert_eq!(tm.refresh_many(&keys, Duration::from_millis(100)), 3);
 
-        assert!(tm.extend(&"b", Duration::from_millis(10)));
-        assert!(!tm.extend(&"c", Duration::from_millis(10)));
+        let deadline = Instant::now() + Duration::from_millis(100);
+        for k in ["a", "b", "d"] {
+            assert_eq!(tm.get_value(&k).unwrap().expires(), Some(&deadline));
+        }
+        assert_eq!(tm.ttl("e"), Some(Duration::from_millis(14)));
+        assert!(tm.get_value_unchecked(&"c").is_none());
 
-        MockClock::advance(Duration::from_millis(50));
+        assert_eq!(tm.refresh_many(&[], Duration::from_millis(100)), 0);
+    }
+
+    #[test]
+    fn ttl_histogram() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert(0, 0, Duration::from_millis(5));
+        tm.insert(1, 1, Duration::from_millis(15));
+        tm.insert(2, 2, Duration::from_millis(20));
+        tm.insert(3, 3, Duration::from_millis(30));
+        tm.insert(4, 4, Duration::from_millis(110));
+        tm.insert(5, 5, Duration::from_millis(200));
+        tm.insert_permanent(6, 6);
+
+        MockClock::advance(Duration::from_millis(10));
+        let buckets = [
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+            Duration::from_millis(100),
+        ];
+        assert_eq!(tm.ttl_histogram(&buckets), [2, 1, 1, 2]);
+        assert_eq!(tm.ttl_histogram(&[]), [6]);
+        assert_eq!(tm.len_raw(), 7);
+
+        MockClock::advance(Duration::from_millis(1_000));
+        assert_eq!(tm.ttl_histogram(&buckets), [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn drain_expired() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_stats();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(20));
+        tm.insert("c", 3, Duration::from_millis(30));
+        tm.insert_permanent("d", 4);
+
+        MockClock::advance(Duration::from_millis(21));
+        let mut expired: Vec<_> = tm.drain_expired();
+        expired.sort();
+        assert_eq!(expired, [("a", 1), ("b", 2)]);
+        assert_eq!(tm.stats().evictions, 2);
+
+        let live: std::collections::HashMap<_, _> = tm.snapshot();
+        assert_eq!(live, [("c", 3), ("d", 4)].into());
+        assert_eq!(tm.len_raw(), 2);
+
+        let expired: Vec<_> = tm.drain_expired();
+        assert!(expired.is_empty());
+    }
+
+    #[test]
+    fn insert_keep_ttl() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        assert_eq!(tm.insert_keep_ttl("a", 1, Duration::from_millis(10)), None);
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(10)));
+
+        MockClock::advance(Duration::from_millis(5));
+        assert_eq!(
+            tm.insert_keep_ttl("a", 2, Duration::from_millis(100)),
+            Some(1)
+        );
+        assert_eq!(tm.get(&"a"), Some(2));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(5)));
+
+        tm.insert_permanent("b", 3);
+        assert_eq!(
+            tm.insert_keep_ttl("b", 4, Duration::from_millis(10)),
+            Some(3)
+        );
+        assert_eq!(tm.get_value(&"b").unwrap().expires(), None);
+
+        MockClock::advance(Duration::from_millis(6));
+        assert_eq!(tm.get_value_unchecked(&"a").map(|v| v.value()), Some(2));
+        assert_eq!(tm.insert_keep_ttl("a", 5, Duration::from_millis(100)), None);
+        assert_eq!(tm.get(&"a"), Some(5));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn cleanup_and_compact() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_stats();
+        tm.insert("a", vec![1, 5, 10, 20], Duration::from_millis(100));
+        tm.insert("b", vec![2, 30], Duration::from_millis(100));
+        tm.insert("c", vec![3], Duration::from_millis(10));
+
+        MockClock::advance(Duration::from_millis(11));
+        let mut compacted = Vec::new();
+        let removed = tm.cleanup_and_compact(|k, events| {
+            compacted.push(*k);
+            events.retain(|&e| e >= 10);
+        });
+        assert_eq!(removed, 1);
+
+        compacted.sort();
+        assert_eq!(compacted, ["a", "b"]);
+        assert_eq!(tm.get(&"a"), Some(vec![10, 20]));
+        assert_eq!(tm.get(&"b"), Some(vec![30]));
+        assert!(tm.get_value_unchecked(&"c").is_none());
+        assert_eq!(tm.stats().evictions, 1);
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(89)));
+    }
+
+    #[test]
+    fn first_and_last_expiring() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        assert_eq!(tm.first_expiring(), None);
+        assert_eq!(tm.last_expiring(), None);
+
+        tm.insert_permanent("p", 0);
+        assert_eq!(tm.first_expiring(), None);
+        assert_eq!(tm.last_expiring(), None);
+
+        tm.insert("a", 1, Duration::from_millis(5));
+        tm.insert("b", 2, Duration::from_millis(20));
+        tm.insert("c", 3, Duration::from_millis(50));
+        tm.insert("d", 4, Duration::from_millis(30));
+        assert_eq!(tm.first_expiring(), Some(("a", Duration::from_millis(5))));
+        assert_eq!(tm.last_expiring(), Some(("c", Duration::from_millis(50))));
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.first_expiring(), Some(("b", Duration::from_millis(10))));
+        assert_eq!(tm.last_expiring(), Some(("c", Duration::from_millis(40))));
+
+        MockClock::advance(Duration::from_millis(41));
+        assert_eq!(tm.first_expiring(), None);
+        assert_eq!(tm.last_expiring(), None);
+    }
+
+    #[test]
+    fn modify() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", vec![1], Duration::from_millis(10));
+        tm.insert_permanent("p", vec![0]);
+
+        assert!(tm.modify(&"a", |v, deadline| {
+            v.push(2);
+            *deadline += Duration::from_millis(20);
+        }));
+        assert_eq!(tm.get(&"a"), Some(vec![1, 2]));
+        assert_eq!(tm.ttl(&"a"), Some(Duration::from_millis(30)));
+
+        MockClock::advance(Duration::from_millis(25));
+        assert!(tm.modify(&"a", |v, deadline| {
+            v.clear();
+            *deadline = Instant::now();
+        }));
+        assert_eq!(tm.get(&"a"), Some(vec![]));
+
+        MockClock::advance(Duration::from_millis(1));
+        assert!(!tm.modify(&"a", |_, _| panic!("expired value modified")));
+        assert_eq!(tm.len_raw(), 1);
+
+        assert!(!tm.modify(&"p", |_, _| panic!("permanent value modified")));
+        assert!(!tm.modify(&"missing", |_, _| panic!("missing value modified")));
+        assert_eq!(tm.get(&"p"), Some(vec![0]));
+    }
+
+    #[test]
+    fn static_map() {
+        use std::hash::{BuildHasherDefault, DefaultHasher};
+
+        type Map = TimedMap<&'static str, i32, Instant, BuildHasherDefault<DefaultHasher>>;
+        static TM: Map = TimedMap::new_with_timesource_and_hasher(BuildHasherDefault::new());
+
+        TM.insert("a", 1, Duration::from_millis(10));
+        assert_eq!(TM.get(&"a"), Some(1));
+        assert_eq!(TM.len(), 1);
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(TM.get(&"a"), None);
+    }
+
+    #[test]
+    fn remove_if() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(10));
+        tm.insert("c", 3, Duration::from_millis(5));
+
+        assert_eq!(tm.remove_if(&"a", |v| *v == 1), Some(1));
         assert_eq!(tm.get(&"a"), None);
+
+        assert_eq!(tm.remove_if(&"b", |v| *v == 1), None);
         assert_eq!(tm.get(&"b"), Some(2));
-        assert_eq!(tm.len(), 1);
-        assert!(!tm.is_empty());
 
-        MockClock::advance(Duration::from_millis(50));
-        assert_eq!(tm.get(&"a"), None);
-        assert_eq!(tm.get(&"b"), None);
-        assert_eq!(tm.len(), 0);
-        assert!(tm.is_empty());
+        assert_eq!(tm.remove_if(&"missing", |_| true), None);
+
+        MockClock::advance(Duration::from_millis(6));
+        assert_eq!(
+            tm.remove_if(&"c", |_| panic!("expired value checked")),
+            None
+        );
+        assert_eq!(tm.len_raw(), 1);
     }
 
     #[test]
-    fn cleanup() {
+    fn expired_count() {
         let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        assert_eq!(tm.expired_count(), 0);
 
-        tm.insert("a", 1, Duration::from_millis(5));
+        tm.insert("a", 1, Duration::from_millis(10));
         tm.insert("b", 2, Duration::from_millis(10));
-        tm.insert("c", 3, Duration::from_millis(15));
+        tm.insert("c", 3, Duration::from_millis(20));
+        tm.insert_permanent("p", 0);
+        assert_eq!(tm.expired_count(), 0);
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.expired_count(), 2);
+        assert_eq!(tm.expired_count(), tm.len_raw() - tm.len());
+
+        assert_eq!(tm.cleanup(), 2);
+        assert_eq!(tm.expired_count(), 0);
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.expired_count(), 1);
+    }
+
+    #[test]
+    fn capacity_policy() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource()
+            .with_max_capacity(3)
+            .with_eviction_policy(|pairs| pairs.map(|(k, _)| *k).min());
+        tm.insert("c", 1, Duration::from_millis(100));
+        tm.insert("b", 2, Duration::from_millis(100));
+        tm.insert("d", 3, Duration::from_millis(10));
+
+        tm.insert("e", 4, Duration::from_millis(100));
+        assert_eq!(tm.get("b"), None);
         assert_eq!(tm.len(), 3);
-        assert!(!tm.is_empty());
 
-        tm.cleanup();
-        assert!(tm.contains(&"a"));
-        assert!(tm.contains(&"b"));
-        assert!(tm.contains(&"c"));
+        tm.insert("a", 5, Duration::from_millis(100));
+        assert_eq!(tm.get("c"), None);
         assert_eq!(tm.len(), 3);
-        assert!(!tm.is_empty());
 
-        MockClock::advance(Duration::from_millis(6));
-        tm.cleanup();
-        assert!(!tm.contains(&"a"));
-        assert!(tm.contains(&"b"));
-        assert!(tm.contains(&"c"));
+        MockClock::advance(Duration::from_millis(20));
+        tm.insert("f", 6, Duration::from_millis(100));
+        assert!(tm.get_value_unchecked("d").is_none());
+        assert_eq!(tm.get("a"), Some(5));
+        assert_eq!(tm.get("e"), Some(4));
+        assert_eq!(tm.get("f"), Some(6));
+    }
+
+    #[test]
+    fn capacity_policy_fallback() {
+        let tm = TimedMap::new_with_capacity_and_policy(2, |_| Some("missing"));
+        tm.insert("a", 1, Duration::from_secs(60));
+        tm.insert("b", 2, Duration::from_secs(60));
+        assert_eq!(tm.get("b"), Some(2));
+
+        tm.insert("c", 3, Duration::from_secs(60));
+        assert_eq!(tm.get("a"), None);
         assert_eq!(tm.len(), 2);
-        assert!(!tm.is_empty());
+    }
 
-        MockClock::advance(Duration::from_millis(5));
-        tm.cleanup();
-        assert!(!tm.contains(&"a"));
-        assert!(!tm.contains(&"b"));
-        assert!(tm.contains(&"c"));
-        assert_eq!(tm.len(), 1);
-        assert!(!tm.is_empty());
+    #[test]
+    fn read_guard() {
+        /// Panics when cloned, so the test fails if a value is cloned.
+        #[derive(Debug, PartialEq)]
+        struct NoClone(i32);
 
-        MockClock::advance(Duration::from_millis(5));
-        tm.cleanup();
-        assert!(!tm.contains(&"a"));
-        assert!(!tm.contains(&"b"));
-        assert!(!tm.contains(&"c"));
-        assert_eq!(tm.len(), 0);
+        impl Clone for NoClone {
+            fn clone(&self) -> Self {
+                panic!("value cloned");
+            }
+        }
+
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        tm.insert("a", NoClone(1), Duration::from_millis(10));
+        tm.insert("b", NoClone(2), Duration::from_millis(20));
+        tm.insert("c", NoClone(3), Duration::from_millis(20));
+        tm.insert_permanent("p", NoClone(4));
+
+        MockClock::advance(Duration::from_millis(11));
+        let m = tm.read();
+        assert_eq!(m.len(), 3);
+        assert!(!m.is_empty());
+        assert_eq!((&m).into_iter().map(|(_, v)| v.0).sum::<i32>(), 9);
+        assert_eq!(m.get(&"a"), None);
+        assert_eq!(m.get(&"b"), Some(&NoClone(2)));
+        assert!(m.contains_key(&"p"));
+
+        let mut keys: Vec<_> = m.iter().map(|(k, _)| *k).collect();
+        keys.sort();
+        assert_eq!(keys, ["b", "c", "p"]);
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(m.len(), 3);
+        drop(m);
+        assert_eq!(tm.read().len(), 1);
+        assert_eq!(tm.len_raw(), 4);
+    }
+
+    #[test]
+    fn invalidate_all() {
+        let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_on_evict({
+            let evicted = evicted.clone();
+            move |k: &&str, _: &i32| evicted.lock().unwrap().push(*k)
+        });
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_secs(60));
+        tm.insert("c", 3, Duration::from_millis(5));
+        tm.insert_permanent("p", 4);
+
+        MockClock::advance(Duration::from_millis(6));
+        let deadline = tm.get_value_unchecked("c").unwrap().expires().cloned();
+        tm.invalidate_all();
+        assert_eq!(
+            tm.get_value_unchecked("c").unwrap().expires().cloned(),
+            deadline
+        );
+        assert_eq!(tm.len_raw(), 4);
+
+        for key in ["a", "b", "c", "p"] {
+            assert_eq!(
+                tm.get_value_unchecked(key).map(|v| v.is_expired()),
+                Some(true)
+            );
+        }
+        assert_eq!(tm.get(&"a"), None);
+        assert_eq!(tm.get(&"b"), None);
+        assert_eq!(tm.get(&"p"), None);
         assert!(tm.is_empty());
+        assert_eq!(tm.len_raw(), 1);
+
+        assert_eq!(tm.cleanup(), 1);
+        assert_eq!(tm.len_raw(), 0);
+        let mut evicted = evicted.lock().unwrap().clone();
+        evicted.sort();
+        assert_eq!(evicted, ["a", "b", "c", "p"]);
     }
 
     #[test]
-    fn clear() {
+    fn invalidate_all_inclusive() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_inclusive_deadline();
+        tm.insert("a", 1, Duration::from_secs(60));
+        tm.invalidate_all();
+        assert_eq!(tm.get(&"a"), None);
+    }
+
+    #[test]
+    fn invalidate_matching() {
         let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource();
+        for (i, key) in ["a", "b", "c"].iter().enumerate() {
+            tm.insert(format!("tenant:1:{key}"), i, Duration::from_secs(60));
+            tm.insert(format!("tenant:2:{key}"), i, Duration::from_secs(60));
+        }
+        tm.insert("tenant:1:d".to_string(), 3, Duration::from_millis(5));
+        tm.insert_permanent("tenant:1:e".to_string(), 4);
+
+        MockClock::advance(Duration::from_millis(6));
+        assert_eq!(tm.invalidate_matching(|k| k.starts_with("tenant:1:")), 4);
 
-        tm.insert("a", 1, Duration::from_millis(5));
-        tm.insert("b", 2, Duration::from_millis(10));
-        tm.insert("c", 3, Duration::from_millis(15));
         assert_eq!(tm.len(), 3);
-        assert!(!tm.is_empty());
+        assert!(tm.keys().iter().all(|k| k.starts_with("tenant:2:")));
+        assert_eq!(tm.get("tenant:1:a"), None);
+        assert_eq!(tm.get("tenant:2:a"), Some(0));
+        assert_eq!(tm.invalidate_matching(|k| k.starts_with("tenant:1:")), 0);
 
-        tm.clear();
+        assert_eq!(tm.cleanup(), 4);
+        assert_eq!(tm.len_raw(), 3);
+    }
 
-        assert!(!tm.contains(&"a"));
-        assert!(!tm.contains(&"b"));
-        assert!(!tm.contains(&"c"));
-        assert_eq!(tm.len(), 0);
-        assert!(tm.is_empty());
+    #[test]
+    fn entry_reports_expired() {
+        let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let tm: TimedMap<_, _, Instant> =
+            TimedMap::new_with_timesource().with_stats().with_on_evict({
+                let evicted = evicted.clone();
+                move |k: &&str, v: &i32| evicted.lock().unwrap().push((*k, *v))
+            });
+        let rx = tm.expiration_channel();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(10));
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(
+            tm.get_or_insert_with("a", Duration::from_millis(10), || 3),
+            3
+        );
+        assert!(matches!(tm.entry("b"), Entry::Vacant(_)));
+
+        assert_eq!(*evicted.lock().unwrap(), [("a", 1), ("b", 2)]);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [("a", 1), ("b", 2)]);
+        assert_eq!(tm.stats().evictions, 2);
+    }
+
+    #[test]
+    fn compute_if_present_in_place() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource().with_access_tracking();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.get("a");
+        tm.get("a");
+
+        assert_eq!(tm.compute_if_present("a", |_, v| Some(v + 1)), Some(2));
+        assert_eq!(tm.access_stats("a").map(|(n, _)| n), Some(3));
+
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            tm.compute_if_present("a", |_, _| -> Option<i32> { panic!("compute failed") })
+        }));
+        assert!(r.is_err());
+        assert_eq!(tm.get("a"), Some(2));
+        assert_eq!(tm.ttl("a"), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn shrink_threshold_fractional() {
+        let tm: TimedMap<_, _, Instant> =
+            TimedMap::new_with_timesource().with_shrink_threshold(1.5, 16);
+        for i in 0..1_000 {
+            tm.insert(i, i, Duration::from_millis(10));
+        }
+        for i in 1_000..1_600 {
+            tm.insert(i, i, Duration::from_millis(100));
+        }
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.cleanup(), 1_000);
+        assert!(tm.capacity() < 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "shrink ratio must be positive")]
+    fn zero_shrink_threshold() {
+        let _: TimedMap<&str, i32> = TimedMap::new().with_shrink_threshold(0.0, 16);
+    }
+
+    #[test]
+    fn expired_get_not_marked_used() {
+        let tm: TimedMap<_, _, Instant> = TimedMap::new_with_timesource()
+            .with_access_tracking()
+            .with_max_capacity(3)
+            .without_lazy_eviction();
+        tm.insert("a", 1, Duration::from_millis(10));
+        tm.insert("b", 2, Duration::from_millis(20));
+        let accessed = tm.get_value_unchecked("a").unwrap().accessed();
+
+        MockClock::advance(Duration::from_millis(11));
+        assert_eq!(tm.get("a"), None);
+        let v = tm.get_value_unchecked("a").unwrap();
+        assert_eq!(v.access_stats().map(|(n, _)| n), Some(0));
+        assert_eq!(v.accessed(), accessed);
+
+        assert_eq!(tm.get("b"), Some(2));
+        assert_eq!(tm.access_stats("b").map(|(n, _)| n), Some(1));
     }
 }
@@ -1,12 +1,57 @@
-use crate::time::TimeSource;
-use std::time::Duration;
+use crate::{lock::RwLock, time::TimeSource, Expiry};
+use alloc::boxed::Box;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 /// Wraps a map value with a specified
 /// expiry [`TimeSource`].
-#[derive(Debug, Clone)]
+///
+/// A value without expiry never expires.
+///
+/// Each value also carries an 8 byte access counter, which is used
+/// to find the least recently used value in maps with a maximum
+/// capacity. It is kept regardless of whether a capacity is set,
+/// so that values can be moved between maps without conversion.
+/// The number and time of accesses are only tracked in maps with
+/// access tracking enabled.
+#[derive(Debug)]
 pub struct Value<V, TS> {
     value: V,
-    expires: TS,
+    expires: Option<TS>,
+    /// Set by [`invalidate`](Value::invalidate), so the value is
+    /// expired even at its deadline.
+    invalidated: bool,
+    accessed: AtomicU64,
+    access_log: Option<Box<RwLock<(u64, TS)>>>,
+}
+
+impl<V: Clone, TS: Clone> Clone for Value<V, TS> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            expires: self.expires.clone(),
+            invalidated: self.invalidated,
+            accessed: AtomicU64::new(self.accessed()),
+            access_log: self
+                .access_log
+                .as_ref()
+                .map(|log| Box::new(RwLock::new(log.read().clone()))),
+        }
+    }
+}
+
+impl<V, TS> Value<V, TS> {
+    /// Returns the tick of the last access to the value,
+    /// used to determine the least recently used value.
+    pub(crate) fn accessed(&self) -> u64 {
+        self.accessed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn touch(&self, tick: u64) {
+        self.accessed.store(tick, Ordering::Relaxed);
+    }
 }
 
 impl<V, TS> Value<V, TS>
@@ -19,13 +64,64 @@ where
     ///
     /// The values expiry is calculated by adding the
     /// specified lifetime to the result of `TS:now()`.
+    /// If the expiry can not be represented by the time
+    /// source, the value never expires.
     pub fn new(value: V, lifetime: Duration) -> Self {
         Self {
             value,
-            expires: TS::now() + lifetime,
+            expires: TS::now().checked_add(lifetime),
+            invalidated: false,
+            accessed: AtomicU64::new(0),
+            access_log: None,
+        }
+    }
+
+    /// Creates a new [`Value`] with the given inner
+    /// value which expires at the given deadline.
+    pub fn new_at(value: V, deadline: TS) -> Self {
+        Self {
+            value,
+            expires: Some(deadline),
+            invalidated: false,
+            accessed: AtomicU64::new(0),
+            access_log: None,
+        }
+    }
+
+    /// Creates a new [`Value`] with the given inner
+    /// value which never expires.
+    pub fn new_permanent(value: V) -> Self {
+        Self {
+            value,
+            expires: None,
+            invalidated: false,
+            accessed: AtomicU64::new(0),
+            access_log: None,
         }
     }
 
+    /// Starts tracking the number and time of accesses,
+    /// discarding previously tracked accesses.
+    pub(crate) fn track_accesses(&mut self) {
+        self.access_log = Some(Box::new(RwLock::new((0, TS::now()))));
+    }
+
+    /// Counts an access at the current time, if accesses
+    /// are tracked.
+    pub(crate) fn record_access(&self) {
+        if let Some(log) = &self.access_log {
+            let mut log = log.write();
+            log.0 += 1;
+            log.1 = TS::now();
+        }
+    }
+
+    /// Returns the number of accesses and the time of the last
+    /// access, or of the insertion if there was none yet.
+    pub(crate) fn access_stats(&self) -> Option<(u64, TS)> {
+        self.access_log.as_ref().map(|log| log.read().clone())
+    }
+
     /// Returns `true` when the specified expiry is
     /// after the current time.
     pub fn is_expired(&self) -> bool {
@@ -33,27 +129,127 @@ where
     }
 
     /// Returns `true` when the specified expiry is
-    /// after the given time `at`.
+    /// before the given time `at`.
+    ///
+    /// In contrast to [`is_expired`](Value::is_expired), this does
+    /// not read the current time, so many values can be checked
+    /// against a single captured time.
+    ///
+    /// # Example
+    /// ```
+    /// use timedmap::Value;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let values = [
+    ///     Value::new("foo", Duration::ZERO),
+    ///     Value::new("bar", Duration::from_secs(60)),
+    /// ];
+    /// std::thread::sleep(Duration::from_millis(1));
+    ///
+    /// let now = Instant::now();
+    /// let expired: Vec<_> = values.iter().filter(|v| v.is_expired_at(&now)).collect();
+    /// assert_eq!(expired.len(), 1);
+    /// assert_eq!(expired[0].value_ref(), &"foo");
+    /// ```
     pub fn is_expired_at(&self, at: &TS) -> bool {
-        at > &self.expires
+        self.invalidated || self.expires.as_ref().is_some_and(|e| at > e)
+    }
+
+    /// Returns `true` when the specified expiry is
+    /// at or before the given time `at`.
+    ///
+    /// In contrast to [`is_expired_at`](Value::is_expired_at),
+    /// the value is already expired exactly at its deadline.
+    pub fn is_expired_inclusive_at(&self, at: &TS) -> bool {
+        self.invalidated || self.expires.as_ref().is_some_and(|e| at >= e)
+    }
+
+    pub(crate) fn is_expired_at_with(&self, at: &TS, inclusive: bool) -> bool {
+        if inclusive {
+            self.is_expired_inclusive_at(at)
+        } else {
+            self.is_expired_at(at)
+        }
+    }
+
+    /// Returns `true` when the value never expires.
+    pub fn is_permanent(&self) -> bool {
+        self.expires.is_none()
     }
 
     /// Returns a reference to the values expiry
-    /// [`TimeSource`].
-    pub fn expires(&self) -> &TS {
-        &self.expires
+    /// [`TimeSource`] or [`None`] if the value
+    /// never expires.
+    pub fn expires(&self) -> Option<&TS> {
+        self.expires.as_ref()
+    }
+
+    /// Returns the expiry of the value, which can be converted
+    /// between the deadline and the remaining lifetime.
+    pub fn expiry(&self) -> Expiry<TS> {
+        self.expires.clone().into()
+    }
+
+    /// Returns the remaining lifetime of the value
+    /// until it expires or [`None`] if the value
+    /// never expires.
+    ///
+    /// Returns [`Duration::ZERO`] if the value has
+    /// already expired.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.remaining_at(&TS::now())
+    }
+
+    /// Returns the remaining lifetime of the value from `now`
+    /// or [`None`] if the value never expires.
+    ///
+    /// Returns [`Duration::ZERO`] if the value expires before `now`.
+    pub fn remaining_at(&self, now: &TS) -> Option<Duration> {
+        self.expires
+            .as_ref()
+            .map(|e| e.saturating_duration_since(now))
     }
 
     /// Sets the expiry of the value to now plus the
     /// given lifetime.
+    ///
+    /// This also applies to values which did not
+    /// expire before. If the expiry can not be
+    /// represented by the time source, the value
+    /// never expires.
     pub fn set_expiry(&mut self, lifetime: Duration) {
-        self.expires = TS::now() + lifetime;
+        self.expires = TS::now().checked_add(lifetime);
+        self.invalidated = false;
+    }
+
+    /// Sets the expiry of the value to the given deadline or
+    /// makes it never expire.
+    pub(crate) fn set_deadline(&mut self, deadline: Option<TS>) {
+        self.expires = deadline;
+        self.invalidated = false;
+    }
+
+    /// Expires the value immediately, also if it is checked exactly
+    /// at `now`, and moves its deadline to `now` unless it has
+    /// expired before.
+    pub(crate) fn invalidate(&mut self, now: TS) {
+        if self.expires.as_ref().is_none_or(|e| e >= &now) {
+            self.expires = Some(now);
+        }
+        self.invalidated = true;
     }
 
     /// Adds the given duration to the values
     /// expiry.
+    ///
+    /// Values which never expire stay unchanged. If the
+    /// new expiry can not be represented by the time source,
+    /// the value never expires.
     pub fn add_expiry(&mut self, lifetime: Duration) {
-        self.expires += lifetime;
+        if let Some(e) = &self.expires {
+            self.expires = e.checked_add(lifetime);
+            self.invalidated = false;
+        }
     }
 
     /// Returns a copy of the inner value.
@@ -61,11 +257,31 @@ where
         self.value.clone()
     }
 
+    pub(crate) fn into_value(self) -> V {
+        self.value
+    }
+
     /// Returns a reference to the inner value.
     pub fn value_ref(&self) -> &V {
         &self.value
     }
 
+    /// Returns a mutable reference to the inner value.
+    ///
+    /// Values returned from [`get_value`](crate::TimedMap::get_value)
+    /// are detached copies, so changing them does not affect the
+    /// map. Use [`modify`](crate::TimedMap::modify) to change a value
+    /// in the map.
+    pub fn value_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+
+    /// Returns mutable references to the inner value and the
+    /// expiry, which is [`None`] if the value never expires.
+    pub(crate) fn parts_mut(&mut self) -> (&mut V, Option<&mut TS>) {
+        (&mut self.value, self.expires.as_mut())
+    }
+
     /// Returns a copy of the inner value if
     /// the expiry has not yet exceeded.
     pub fn value_checked(&self) -> Option<V> {
@@ -95,7 +311,10 @@ mod test {
     #[test]
     fn expiry() {
         let v: Value<_, Instant> = Value::new("foo", Duration::from_millis(100));
-        assert_eq!(v.expires(), &(Instant::now() + Duration::from_millis(100)));
+        assert_eq!(
+            v.expires(),
+            Some(&(Instant::now() + Duration::from_millis(100)))
+        );
         assert!(!v.is_expired());
         assert_eq!(v.value_checked(), Some("foo"));
 
@@ -109,4 +328,114 @@ mod test {
         assert!(v.is_expired());
         assert_eq!(v.value_checked(), None);
     }
+
+    #[test]
+    fn remaining() {
+        let v: Value<_, Instant> = Value::new("foo", Duration::from_millis(100));
+        assert_eq!(v.remaining(), Some(Duration::from_millis(100)));
+
+        MockClock::advance(Duration::from_millis(40));
+        assert_eq!(v.remaining(), Some(Duration::from_millis(60)));
+
+        MockClock::advance(Duration::from_millis(100));
+        assert_eq!(v.remaining(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn permanent() {
+        let mut v: Value<_, Instant> = Value::new_permanent("foo");
+        assert!(v.is_permanent());
+        assert_eq!(v.expires(), None);
+        assert_eq!(v.remaining(), None);
+
+        MockClock::advance(Duration::from_secs(1_000_000));
+        assert!(!v.is_expired());
+        assert_eq!(v.value_checked(), Some("foo"));
+
+        v.add_expiry(Duration::from_millis(10));
+        assert!(v.is_permanent());
+
+        v.set_expiry(Duration::from_millis(10));
+        assert!(!v.is_permanent());
+        assert_eq!(v.remaining(), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn overflowing_lifetime() {
+        let mut v: Value<_, Instant> = Value::new("foo", Duration::from_secs(u64::MAX));
+        MockClock::advance(Duration::from_secs(1_000_000));
+        assert!(!v.is_expired());
+
+        v.add_expiry(Duration::from_secs(u64::MAX));
+        assert!(v.is_permanent());
+
+        v.set_expiry(Duration::MAX);
+        assert!(v.is_permanent());
+        assert!(!v.is_expired());
+    }
+
+    #[test]
+    fn inclusive_expiry() {
+        let v: Value<_, Instant> = Value::new("foo", Duration::from_millis(100));
+        let deadline = *v.expires().unwrap();
+        assert!(!v.is_expired_at(&deadline));
+        assert!(v.is_expired_inclusive_at(&deadline));
+
+        let before = deadline - Duration::from_millis(1);
+        assert!(!v.is_expired_inclusive_at(&before));
+    }
+
+    #[test]
+    fn is_expired_at_matches_is_expired() {
+        let values: Vec<Value<_, Instant>> = vec![
+            Value::new(1, Duration::from_millis(10)),
+            Value::new(2, Duration::from_millis(20)),
+            Value::new(3, Duration::from_millis(30)),
+            Value::new_permanent(4),
+        ];
+
+        for _ in 0..4 {
+            let now = Instant::now();
+            for v in &values {
+                assert_eq!(v.is_expired_at(&now), v.is_expired());
+            }
+            MockClock::advance(Duration::from_millis(10));
+        }
+
+        let earlier = Instant::now() - Duration::from_millis(25);
+        assert!(values[0].is_expired_at(&earlier));
+        assert!(!values[2].is_expired_at(&earlier));
+        assert!(!values[3].is_expired_at(&earlier));
+    }
+
+    #[test]
+    fn value_mut() {
+        let tm: crate::TimedMap<_, _, Instant> = crate::TimedMap::new_with_timesource();
+        tm.insert("a", 1, Duration::from_millis(10));
+
+        let mut v = tm.get_value(&"a").unwrap();
+        *v.value_mut() = 2;
+        v.set_expiry(Duration::from_millis(100));
+        assert_eq!(v.value(), 2);
+        assert_eq!(tm.get(&"a"), Some(1));
+        assert_eq!(tm.ttl(&"a"), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn invalidate() {
+        let mut v: Value<_, Instant> = Value::new("foo", Duration::from_millis(100));
+        v.invalidate(Instant::now());
+        assert!(v.is_expired());
+        assert_eq!(v.remaining(), Some(Duration::ZERO));
+
+        v.set_expiry(Duration::from_millis(10));
+        assert!(!v.is_expired());
+
+        let mut v: Value<_, Instant> = Value::new("foo", Duration::ZERO);
+        let deadline = Instant::now();
+        MockClock::advance(Duration::from_millis(5));
+        v.invalidate(Instant::now());
+        assert_eq!(v.expires(), Some(&deadline));
+        assert!(v.is_expired());
+    }
 }
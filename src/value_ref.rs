@@ -0,0 +1,62 @@
+use crate::{
+    compat::{DefaultHashBuilder, HashMap},
+    lock::RwLockReadGuard,
+    time::TimeSource,
+    Value,
+};
+use core::{
+    hash::{BuildHasher, Hash},
+    ops::Deref,
+};
+
+type Guard<'a, K, V, TS, S> = RwLockReadGuard<'a, HashMap<K, Value<V, TS>, S>>;
+
+/// A reference to a non-expired value of a
+/// [`TimedMap`](crate::TimedMap).
+///
+/// The reference holds the maps read lock until it is dropped,
+/// so the value can be accessed without cloning it. Writes to the
+/// map are blocked as long as the reference is alive.
+///
+/// This is constructed from the [`get_ref`](crate::TimedMap::get_ref)
+/// method on [`TimedMap`](crate::TimedMap).
+pub struct ValueRef<'a, K, V, TS, S = DefaultHashBuilder> {
+    guard: Guard<'a, K, V, TS, S>,
+    key: K,
+}
+
+impl<'a, K, V, TS, S> ValueRef<'a, K, V, TS, S>
+where
+    K: Eq + Hash,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    pub(crate) fn new(guard: Guard<'a, K, V, TS, S>, key: K) -> Self {
+        Self { guard, key }
+    }
+
+    /// Returns a reference to the key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns a reference to the value.
+    pub fn value(&self) -> &V {
+        self.guard.get(&self.key).expect("entry exists").value_ref()
+    }
+}
+
+impl<K, V, TS, S> Deref for ValueRef<'_, K, V, TS, S>
+where
+    K: Eq + Hash,
+    V: Clone,
+    TS: TimeSource,
+    S: BuildHasher,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
@@ -0,0 +1,519 @@
+use crate::{lock::RwLock, time::TimeSource, Cleanup, Value};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    mem,
+    time::{Duration, Instant},
+};
+
+/// Number of bits used to index the slots of one wheel level.
+const SLOT_BITS: u32 = 6;
+/// Number of slots per wheel level.
+const SLOTS: usize = 1 << SLOT_BITS;
+/// Number of wheel levels. With the default resolution of one
+/// millisecond, this covers lifetimes of roughly two years before
+/// entries are re-scheduled on the top level.
+const LEVELS: usize = 6;
+
+/// Provides a hash map with expiring key-value pairs like
+/// [`TimedMap`](crate::TimedMap), which tracks the expiries of
+/// its entries in a hierarchical timing wheel.
+///
+/// In contrast to [`TimedMap`](crate::TimedMap), a [`cleanup`](Cleanup::cleanup)
+/// does not scan the whole map but only visits the wheel slots which
+/// have elapsed since the last cleanup. Therefore, the cost of a
+/// cleanup depends on the number of expired entries and the elapsed
+/// time instead of the total size of the map.
+///
+/// Expiries are tracked in ticks of a configurable resolution, which
+/// defaults to one millisecond. Expired entries are only removed by
+/// a cleanup after the tick containing their expiry has elapsed.
+///
+/// # Example
+/// ```
+/// use timedmap::{Cleanup, TimedWheelMap};
+/// use std::time::Duration;
+///
+/// let tm = TimedWheelMap::new();
+/// tm.insert("foo", "bar", Duration::from_millis(10));
+/// assert_eq!(tm.get(&"foo"), Some("bar"));
+///
+/// std::thread::sleep(Duration::from_millis(20));
+/// assert_eq!(tm.cleanup(), 1);
+/// assert_eq!(tm.get(&"foo"), None);
+/// ```
+#[derive(Debug)]
+pub struct TimedWheelMap<K, V, TS = Instant> {
+    inner: RwLock<Wheel<K, V, TS>>,
+}
+
+#[derive(Debug)]
+struct Wheel<K, V, TS> {
+    map: HashMap<K, WheelEntry<V, TS>>,
+    /// Slots of each level containing the keys scheduled in them.
+    /// Each key is contained in at most one slot, which is tracked
+    /// by the [`SlotHandle`] of its entry.
+    levels: Vec<Vec<Vec<K>>>,
+    origin: TS,
+    resolution: Duration,
+    /// The last tick which has been processed.
+    current: u64,
+}
+
+#[derive(Debug)]
+struct WheelEntry<V, TS> {
+    value: Value<V, TS>,
+    /// The slot the key is scheduled in or [`None`] if the
+    /// value never expires.
+    slot: Option<SlotHandle>,
+}
+
+/// Position of a key in the wheel and the tick it is due in.
+#[derive(Debug, Clone, Copy)]
+struct SlotHandle {
+    level: usize,
+    slot: usize,
+    index: usize,
+    tick: u64,
+}
+
+impl<K, V> TimedWheelMap<K, V> {
+    /// Create a new instance of [`TimedWheelMap`] with the default
+    /// [`TimeSource`] implementation [`Instant`].
+    pub fn new() -> Self {
+        Self::new_with_timesource()
+    }
+}
+
+impl<K, V> Default for TimedWheelMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, TS> TimedWheelMap<K, V, TS>
+where
+    TS: TimeSource,
+{
+    /// Create a new instance of [`TimedWheelMap`] with a custom
+    /// [`TimeSource`] implementation.
+    pub fn new_with_timesource() -> Self {
+        Self::new_with_resolution(Duration::from_millis(1))
+    }
+
+    /// Create a new instance of [`TimedWheelMap`] with a custom
+    /// [`TimeSource`] implementation and the given tick resolution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resolution is zero.
+    pub fn new_with_resolution(resolution: Duration) -> Self {
+        assert!(!resolution.is_zero(), "resolution must not be zero");
+        Self {
+            inner: RwLock::new(Wheel {
+                map: HashMap::new(),
+                levels: (0..LEVELS)
+                    .map(|_| (0..SLOTS).map(|_| vec![]).collect())
+                    .collect(),
+                origin: TS::now(),
+                resolution,
+                current: 0,
+            }),
+        }
+    }
+}
+
+impl<K, V, TS> TimedWheelMap<K, V, TS>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+{
+    /// Add a new key-value pair to the map with the
+    /// given lifetime.
+    ///
+    /// Returns the previous value for the given key if there
+    /// was one and it has not been expired.
+    pub fn insert(&self, key: K, value: V, lifetime: Duration) -> Option<V> {
+        let mut w = self.inner.write();
+        let prev = w.take(&key);
+        let value = Value::new(value, lifetime);
+        let slot = value.expires().map(|e| {
+            let tick = w.tick_of(e);
+            w.schedule(key.clone(), tick)
+        });
+        w.map.insert(key, WheelEntry { value, slot });
+        prev.and_then(|v| v.value_checked())
+    }
+
+    /// Returns a copy of the value corresponding to the
+    /// given key.
+    ///
+    /// [`None`] is returned when the values lifetime has
+    /// been passed.
+    ///
+    /// # Behavior
+    ///
+    /// If the key-value pair has expired and not been
+    /// cleaned up before, it will be removed from the
+    /// map on next retrival try.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        {
+            let w = self.inner.read();
+            match w.map.get(key).map(|e| &e.value) {
+                Some(v) if !v.is_expired() => return Some(v.value()),
+                Some(_) => {}
+                None => return None,
+            }
+        }
+        // The value may have been replaced since the read lock has
+        // been released, so it is only removed if it is still expired.
+        let mut w = self.inner.write();
+        if w.map.get(key).is_some_and(|e| e.value.is_expired()) {
+            w.take(key);
+        }
+        None
+    }
+
+    /// Returns `true` when the map contains a non-expired
+    /// value for the given key.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Removes the given key-value pair from the map and
+    /// returns the value if it was previously in the map
+    /// and is not expired.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut w = self.inner.write();
+        w.take(key).and_then(|v| v.value_checked())
+    }
+
+    /// Returns the number of key-value pairs in the map
+    /// which have not been expired.
+    pub fn len(&self) -> usize {
+        let w = self.inner.read();
+        w.map.values().filter(|e| !e.value.is_expired()).count()
+    }
+
+    /// Returns `true` when the map does not contain any
+    /// non-expired key-value pair.
+    pub fn is_empty(&self) -> bool {
+        let w = self.inner.read();
+        w.map.values().all(|e| e.value.is_expired())
+    }
+
+    /// Clears the map, removing all key-value pairs.
+    pub fn clear(&self) {
+        let mut w = self.inner.write();
+        w.map.clear();
+        w.levels.iter_mut().flatten().for_each(Vec::clear);
+    }
+}
+
+impl<K, V, TS> Wheel<K, V, TS>
+where
+    K: Eq + PartialEq + Hash + Clone,
+    V: Clone,
+    TS: TimeSource,
+{
+    /// Returns the first tick which starts after the given time.
+    fn tick_of(&self, at: &TS) -> u64 {
+        let elapsed = at.saturating_duration_since(&self.origin);
+        let tick = elapsed.as_nanos() / self.resolution.as_nanos() + 1;
+        tick.try_into().unwrap_or(u64::MAX)
+    }
+
+    /// Removes the entry of the given key from the map and
+    /// unlinks it from its slot.
+    fn take<Q>(&mut self, key: &Q) -> Option<Value<V, TS>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.map.remove(key)?;
+        if let Some(handle) = entry.slot {
+            self.unlink(handle);
+        }
+        Some(entry.value)
+    }
+
+    /// Removes the key at the given position from its slot. The
+    /// last key of the slot is moved into the freed position.
+    fn unlink(&mut self, handle: SlotHandle) {
+        let keys = &mut self.levels[handle.level][handle.slot];
+        keys.swap_remove(handle.index);
+        if let Some(moved) = keys.get(handle.index) {
+            if let Some(h) = self.map.get_mut(moved).and_then(|e| e.slot.as_mut()) {
+                h.index = handle.index;
+            }
+        }
+    }
+
+    /// Places the key into the slot for the given tick and
+    /// returns its position.
+    fn schedule(&mut self, key: K, tick: u64) -> SlotHandle {
+        // Entries which are already due are processed with the next tick.
+        let delta = tick.max(self.current + 1) - self.current;
+
+        let level = (0..LEVELS)
+            .find(|&l| delta < 1 << (SLOT_BITS * (l as u32 + 1)))
+            .unwrap_or(LEVELS - 1);
+
+        // Entries exceeding the range of the top level are placed into
+        // its furthest slot and re-scheduled when it is cascaded.
+        let max_delta = (1u64 << (SLOT_BITS * LEVELS as u32)) - 1;
+        let slot_tick = self.current + delta.min(max_delta);
+
+        let slot = slot_index(slot_tick, level);
+        let keys = &mut self.levels[level][slot];
+        keys.push(key);
+        SlotHandle {
+            level,
+            slot,
+            index: keys.len() - 1,
+            tick,
+        }
+    }
+
+    /// Processes all ticks up to the current time and returns
+    /// the number of removed entries.
+    fn advance(&mut self) -> usize {
+        let now = TS::now();
+        let target = self.tick_of(&now) - 1;
+        let mut removed = 0;
+
+        while let Some(tick) = self.next_event().filter(|&t| t <= target) {
+            self.current = tick;
+
+            // Higher levels are cascaded first, because their entries
+            // might be moved into lower level slots due in this tick.
+            for level in (1..LEVELS).rev() {
+                if self.current & ((1 << (SLOT_BITS * level as u32)) - 1) != 0 {
+                    continue;
+                }
+                let slot = slot_index(self.current, level);
+                for key in mem::take(&mut self.levels[level][slot]) {
+                    removed += self.process(key, &now) as usize;
+                }
+            }
+
+            let slot = slot_index(self.current, 0);
+            for key in mem::take(&mut self.levels[0][slot]) {
+                removed += self.process(key, &now) as usize;
+            }
+        }
+
+        self.current = self.current.max(target);
+        removed
+    }
+
+    /// Returns the next tick after the current one where either
+    /// a level 0 slot is due or a non-empty slot of a higher level
+    /// is cascaded.
+    fn next_event(&self) -> Option<u64> {
+        let mut next = None;
+
+        if let Some(d) =
+            (1..SLOTS as u64).find(|d| !self.levels[0][slot_index(self.current + d, 0)].is_empty())
+        {
+            next = Some(self.current + d);
+        }
+
+        for level in 1..LEVELS {
+            let shift = SLOT_BITS * level as u32;
+            let event = (1..=SLOTS as u64)
+                .map(|k| ((self.current >> shift) + k) << shift)
+                .find(|&t| !self.levels[level][slot_index(t, level)].is_empty());
+            if let Some(t) = event {
+                next = Some(next.map_or(t, |n: u64| n.min(t)));
+            }
+        }
+
+        next
+    }
+
+    /// Handles a key which has been taken out of its elapsed slot.
+    /// The entry is removed if it has expired at the given time,
+    /// otherwise it is scheduled again.
+    ///
+    /// Returns `true` if the entry has been removed.
+    fn process(&mut self, key: K, now: &TS) -> bool {
+        let Some(entry) = self.map.get(&key) else {
+            return false;
+        };
+        let tick = entry.slot.map_or(self.current, |h| h.tick);
+
+        if tick <= self.current && entry.value.is_expired_at(now) {
+            self.map.remove(&key);
+            return true;
+        }
+
+        let handle = self.schedule(key.clone(), tick);
+        if let Some(entry) = self.map.get_mut(&key) {
+            entry.slot = Some(handle);
+        }
+        false
+    }
+}
+
+fn slot_index(tick: u64, level: usize) -> usize {
+    (tick >> (SLOT_BITS * level as u32)) as usize & (SLOTS - 1)
+}
+
+impl<K, V, TS> Cleanup for TimedWheelMap<K, V, TS>
+where
+    K: Eq + PartialEq + Hash + Clone + Send + Sync,
+    V: Clone + Send + Sync,
+    TS: TimeSource + Send + Sync,
+{
+    fn cleanup(&self) -> usize {
+        let mut w = self.inner.write();
+        w.advance()
+    }
+
+    fn until_next_expiry(&self) -> Option<Duration> {
+        let now = TS::now();
+        let w = self.inner.read();
+        w.map
+            .values()
+            .filter(|e| !e.value.is_expired_at(&now))
+            .filter_map(|e| e.value.expires())
+            .min()
+            .map(|e| e.saturating_duration_since(&now))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_instant::{Instant, MockClock};
+
+    #[test]
+    fn get_checked() {
+        let tm: TimedWheelMap<_, _, Instant> = TimedWheelMap::new_with_timesource();
+        assert!(tm.is_empty());
+
+        assert_eq!(tm.insert("a", 1, Duration::from_millis(10)), None);
+        assert_eq!(tm.insert("a", 2, Duration::from_millis(10)), Some(1));
+        assert_eq!(tm.len(), 1);
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.get(&"a"), Some(2));
+
+        MockClock::advance(Duration::from_millis(1));
+        assert_eq!(tm.get(&"a"), None);
+        assert!(!tm.contains(&"a"));
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn cleanup() {
+        let tm: TimedWheelMap<_, _, Instant> = TimedWheelMap::new_with_timesource();
+
+        tm.insert("a", 1, Duration::from_millis(5));
+        tm.insert("b", 2, Duration::from_millis(100));
+        tm.insert("c", 3, Duration::from_secs(10));
+        tm.insert("d", 4, Duration::from_secs(100_000));
+        assert_eq!(tm.cleanup(), 0);
+
+        MockClock::advance(Duration::from_millis(6));
+        assert_eq!(tm.cleanup(), 1);
+        assert_eq!(tm.len(), 3);
+
+        MockClock::advance(Duration::from_millis(95));
+        assert_eq!(tm.cleanup(), 1);
+        assert_eq!(tm.len(), 2);
+
+        MockClock::advance(Duration::from_millis(9_899));
+        assert_eq!(tm.cleanup(), 0);
+        assert_eq!(tm.len(), 2);
+
+        MockClock::advance(Duration::from_millis(2));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(tm.contains(&"d"));
+
+        MockClock::advance(Duration::from_secs(100_000));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn cleanup_replaced_and_removed() {
+        let tm: TimedWheelMap<_, _, Instant> = TimedWheelMap::new_with_timesource();
+
+        tm.insert("a", 1, Duration::from_millis(5));
+        tm.insert("a", 2, Duration::from_millis(50));
+        tm.insert("b", 3, Duration::from_millis(5));
+        tm.insert("c", 4, Duration::from_millis(5));
+        assert_eq!(tm.remove(&"b"), Some(3));
+
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.cleanup(), 1);
+        assert_eq!(tm.get(&"a"), Some(2));
+
+        tm.insert("b", 5, Duration::from_millis(5));
+        MockClock::advance(Duration::from_millis(10));
+        assert_eq!(tm.cleanup(), 1);
+        assert_eq!(tm.get(&"a"), Some(2));
+
+        MockClock::advance(Duration::from_millis(40));
+        assert_eq!(tm.cleanup(), 1);
+        assert!(tm.is_empty());
+    }
+
+    #[test]
+    fn slots_track_live_entries() {
+        let tm: TimedWheelMap<_, _, Instant> = TimedWheelMap::new_with_timesource();
+        let scheduled = |tm: &TimedWheelMap<_, _, Instant>| {
+            tm.inner
+                .read()
+                .levels
+                .iter()
+                .flatten()
+                .map(Vec::len)
+                .sum::<usize>()
+        };
+
+        for i in 0..10_000 {
+            tm.insert("a", i, Duration::from_secs(3600));
+        }
+        tm.insert("b", 0, Duration::from_secs(3600));
+        tm.insert("c", 0, Duration::from_secs(3600));
+        assert_eq!(scheduled(&tm), 3);
+
+        tm.remove(&"a");
+        assert_eq!(scheduled(&tm), 2);
+        assert_eq!(tm.get(&"c"), Some(0));
+
+        MockClock::advance(Duration::from_secs(3601));
+        assert_eq!(tm.cleanup(), 2);
+        assert_eq!(scheduled(&tm), 0);
+    }
+
+    #[test]
+    fn until_next_expiry() {
+        let tm: TimedWheelMap<_, _, Instant> = TimedWheelMap::new_with_timesource();
+        assert_eq!(tm.until_next_expiry(), None);
+
+        tm.insert("a", 1, Duration::from_millis(50));
+        tm.insert("b", 2, Duration::from_millis(20));
+        assert_eq!(tm.until_next_expiry(), Some(Duration::from_millis(20)));
+
+        MockClock::advance(Duration::from_millis(30));
+        assert_eq!(tm.until_next_expiry(), Some(Duration::from_millis(20)));
+    }
+}